@@ -0,0 +1,57 @@
+use core::ops::{Deref, DerefMut};
+
+// vetor de capacidade fixa, a contabilidade do heap não pode alocar no próprio
+// heap, senão crescer a lista de páginas entra de novo no alocador com o lock pego
+#[derive(Clone, Copy)]
+pub struct FixedVec<T: Copy, const N: usize> {
+    items: [T; N],
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> FixedVec<T, N> {
+    pub const fn new(fill: T) -> Self {
+        FixedVec { items: [fill; N], len: 0 }
+    }
+
+    pub fn push(&mut self, item: T) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.items[self.len] = item;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.items[self.len])
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        let item = self.items[index];
+        self.items.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        item
+    }
+}
+
+impl<T: Copy, const N: usize> Deref for FixedVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+}
+
+impl<T: Copy, const N: usize> DerefMut for FixedVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items[..self.len]
+    }
+}
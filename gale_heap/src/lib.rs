@@ -0,0 +1,55 @@
+//algoritmos do heap que não dependem do kernel
+//tudo aqui só faz contas sobre endereços e guarda a contabilidade fora do heap, então
+//funciona sobre qualquer região de bytes: o kernel usa com HEAP_START e os testes no
+//host (`cargo test -p gale_heap`) usam endereços quaisquer
+#![cfg_attr(not(test), no_std)]
+
+mod fixed_vec;
+mod page;
+mod span;
+
+pub use fixed_vec::FixedVec;
+pub use page::{Page, PageKind, SlabClass, SlabList};
+pub use span::{LiveSpans, SpanMap};
+
+pub const PAGE_SIZE: usize = 4096; // 4 KiB pages
+pub const SMALL_BLOCK_SIZE: usize = 256; // Threshold for small blocks
+pub const MAX_CACHEABLE_SIZE: usize = 1024; // Defina o tamanho máximo para os blocos de memória alocados que podem ser reutilizados
+pub const MAX_BLOCKS: usize = PAGE_SIZE / SMALL_BLOCK_SIZE + 1; // blocos grandes (> SMALL_BLOCK_SIZE) que cabem numa página
+pub const SIZE_CLASSES: [usize; 7] = [16, 32, 64, 128, 256, 512, MAX_CACHEABLE_SIZE];
+pub const SLAB_CLASSES: usize = 5; // as classes até SMALL_BLOCK_SIZE (16 a 256 bytes) vêm de slabs
+pub const SLAB_BITMAP_WORDS: usize = PAGE_SIZE / 16 / 64; // bits para a menor classe, 256 slots
+pub const NO_PAGE: usize = usize::MAX;
+
+pub fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+// junta blocos livres vizinhos, a lista precisa estar ordenada pelo início
+pub fn merge_free_blocks<const N: usize>(free_blocks: &mut FixedVec<(usize, usize), N>) {
+    let mut i = 0;
+    while i + 1 < free_blocks.len() {
+        if free_blocks[i].0 + free_blocks[i].1 == free_blocks[i + 1].0 {
+            free_blocks[i].1 += free_blocks[i + 1].1;
+            free_blocks.remove(i + 1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+// índice da menor classe que comporta `size`, None se o bloco não vai para o cache
+pub fn size_class(size: usize) -> Option<usize> {
+    SIZE_CLASSES.iter().position(|&class| size <= class)
+}
+
+// tamanho real do bloco entregue para um pedido de `size`, os tamanhos que
+// passam pelo cache são arredondados para a classe
+pub fn block_size(size: usize) -> usize {
+    size_class(size).map_or(size, |class| SIZE_CLASSES[class])
+}
+
+// objetos maiores que uma página, ou que pedem alinhamento de página, vão para os spans
+pub fn is_large_object(size: usize, align: usize) -> bool {
+    size > PAGE_SIZE || align >= PAGE_SIZE
+}
@@ -0,0 +1,271 @@
+use crate::{align_up, merge_free_blocks, FixedVec, MAX_BLOCKS, NO_PAGE, PAGE_SIZE, SIZE_CLASSES, SLAB_BITMAP_WORDS};
+
+// uma página ou é um slab de uma única classe ou guarda blocos grandes, nunca
+// os dois, senão o bitmap e a lista livre entregariam os mesmos bytes
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    Slab(usize),
+    Large,
+}
+
+#[derive(Clone, Copy)]
+pub struct Page {
+    pub start: usize,
+    pub kind: PageKind,
+    pub current_offset: usize,
+    pub allocations: FixedVec<(usize, usize), MAX_BLOCKS>,
+    pub free_blocks: FixedVec<(usize, usize), MAX_BLOCKS>,
+    // um bit por slot do slab, só os primeiros PAGE_SIZE / classe bits são usados
+    pub slab_bitmap: [u64; SLAB_BITMAP_WORDS],
+    pub slab_free: usize,
+    // ligação na lista parcial/cheia/vazia da classe
+    pub slab_prev: usize,
+    pub slab_next: usize,
+}
+
+// lista duplamente ligada de páginas de slab, ligada pelos índices em `pages`
+#[derive(Clone, Copy)]
+pub struct SlabList {
+    pub head: usize,
+}
+
+impl SlabList {
+    pub const fn new() -> Self {
+        SlabList { head: NO_PAGE }
+    }
+
+    pub fn push(&mut self, pages: &mut [Page], index: usize) {
+        pages[index].slab_prev = NO_PAGE;
+        pages[index].slab_next = self.head;
+        if self.head != NO_PAGE {
+            pages[self.head].slab_prev = index;
+        }
+        self.head = index;
+    }
+
+    pub fn remove(&mut self, pages: &mut [Page], index: usize) {
+        let (prev, next) = (pages[index].slab_prev, pages[index].slab_next);
+        if prev != NO_PAGE {
+            pages[prev].slab_next = next;
+        } else {
+            self.head = next;
+        }
+        if next != NO_PAGE {
+            pages[next].slab_prev = prev;
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SlabClass {
+    pub partial: SlabList,
+    pub full: SlabList,
+    pub empty: SlabList,
+}
+
+impl SlabClass {
+    pub const fn new() -> Self {
+        SlabClass {
+            partial: SlabList::new(),
+            full: SlabList::new(),
+            empty: SlabList::new(),
+        }
+    }
+
+    // devolve o slot e move a página entre as listas conforme ela esvazia
+    pub fn free(&mut self, pages: &mut [Page], index: usize, addr: usize) {
+        let was_full = pages[index].slab_free == 0;
+        if !pages[index].free_slot(addr) {
+            return;
+        }
+
+        if was_full {
+            self.full.remove(pages, index);
+            self.partial.push(pages, index);
+        }
+        if pages[index].slab_free == pages[index].slab_slots() {
+            self.partial.remove(pages, index);
+            self.empty.push(pages, index);
+        }
+    }
+}
+
+impl Page {
+    pub const fn new(start: usize, kind: PageKind) -> Self {
+        Page {
+            start,
+            kind,
+            current_offset: 0,
+            allocations: FixedVec::new((0, 0)),
+            free_blocks: FixedVec::new((0, 0)),
+            slab_bitmap: [0; SLAB_BITMAP_WORDS],
+            slab_free: 0,
+            slab_prev: NO_PAGE,
+            slab_next: NO_PAGE,
+        }
+    }
+
+    // prepara a página como slab da classe, com todos os slots livres
+    pub fn init_slab(&mut self, class: usize) {
+        self.kind = PageKind::Slab(class);
+        self.slab_bitmap = [0; SLAB_BITMAP_WORDS];
+        self.slab_free = self.slab_slots();
+    }
+
+    pub fn slab_block_size(&self) -> usize {
+        match self.kind {
+            PageKind::Slab(class) => SIZE_CLASSES[class],
+            PageKind::Large => PAGE_SIZE,
+        }
+    }
+
+    pub fn slab_slots(&self) -> usize {
+        PAGE_SIZE / self.slab_block_size()
+    }
+
+    pub fn alloc_slot(&mut self) -> Option<usize> {
+        let block_size = self.slab_block_size();
+        let slots = self.slab_slots();
+        for (i, word) in self.slab_bitmap.iter_mut().enumerate() {
+            if *word == u64::MAX {
+                continue;
+            }
+            let bit = word.trailing_ones() as usize;
+            let slot = i * 64 + bit;
+            if slot >= slots {
+                return None;
+            }
+            *word |= 1 << bit;
+            self.slab_free -= 1;
+            return Some(self.start + slot * block_size);
+        }
+        None
+    }
+
+    // libera o slot de `ptr`, retorna false se ele já estava livre
+    pub fn free_slot(&mut self, ptr: usize) -> bool {
+        let slot = (ptr - self.start) / self.slab_block_size();
+        let bit = 1 << (slot % 64);
+        let word = &mut self.slab_bitmap[slot / 64];
+        if *word & bit == 0 {
+            return false;
+        }
+        *word &= !bit;
+        self.slab_free += 1;
+        true
+    }
+
+    pub fn alloc_large(&mut self, size: usize, align: usize) -> Option<usize> {
+        if self.allocations.len() == MAX_BLOCKS {
+            return None;
+        }
+
+        for (i, &(offset, block_size)) in self.free_blocks.iter().enumerate() {
+            let aligned_offset = align_up(offset, align);
+            if aligned_offset + size <= offset + block_size {
+                self.free_blocks.remove(i);
+                self.release_range(offset, aligned_offset - offset);
+                self.release_range(aligned_offset + size, offset + block_size - (aligned_offset + size));
+                self.allocations.push((aligned_offset, size));
+                return Some(self.start + aligned_offset);
+            }
+        }
+
+        let current_offset = self.current_offset;
+        let aligned_offset = align_up(current_offset, align);
+        if aligned_offset + size <= PAGE_SIZE {
+            self.current_offset = aligned_offset + size;
+            // o espaço pulado para alinhar também fica livre
+            self.release_range(current_offset, aligned_offset - current_offset);
+            self.allocations.push((aligned_offset, size));
+            Some(self.start + aligned_offset)
+        } else {
+            None
+        }
+    }
+
+    // devolve [offset, offset + size) para a página: colado no fim da área usada ele
+    // só encolhe current_offset, senão é juntado aos blocos livres vizinhos; com o fim
+    // sempre devolvido assim cada bloco livre fica antes de uma alocação, então cabem
+    // em MAX_BLOCKS e o push não falha
+    fn release_range(&mut self, offset: usize, size: usize) -> bool {
+        if size == 0 {
+            return true;
+        }
+
+        if offset + size == self.current_offset {
+            self.current_offset = offset;
+            if let Some(&(last, last_size)) = self.free_blocks.last() {
+                if last + last_size == self.current_offset {
+                    self.current_offset = last;
+                    self.free_blocks.pop();
+                }
+            }
+            return true;
+        }
+
+        if !self.free_blocks.push((offset, size)) {
+            return false;
+        }
+        self.free_blocks.sort_unstable_by_key(|&(offset, _)| offset);
+        merge_free_blocks(&mut self.free_blocks);
+        true
+    }
+
+    // muda o tamanho de um bloco grande sem movê-lo, usando o bloco livre logo
+    // depois dele ou o fim da área já usada da página
+    pub fn resize_large(&mut self, ptr: usize, size: usize, new_size: usize) -> bool {
+        let offset = ptr - self.start;
+        let pos = match self.allocations.iter().position(|&(o, s)| o == offset && s == size) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let end = offset + size;
+
+        if new_size < size {
+            if !self.release_range(offset + new_size, size - new_size) {
+                return false;
+            }
+        } else {
+            let extra = new_size - size;
+            if end == self.current_offset {
+                if offset + new_size > PAGE_SIZE {
+                    return false;
+                }
+                self.current_offset = offset + new_size;
+            } else if let Some(i) = self.free_blocks.iter().position(|&(o, s)| o == end && s >= extra) {
+                let (free_offset, free_size) = self.free_blocks[i];
+                if free_size == extra {
+                    self.free_blocks.remove(i);
+                } else {
+                    self.free_blocks[i] = (free_offset + extra, free_size - extra);
+                }
+            } else {
+                return false;
+            }
+        }
+
+        self.allocations[pos].1 = new_size;
+        true
+    }
+
+    pub fn dealloc_large(&mut self, ptr: usize, size: usize) {
+        let offset = ptr - self.start;
+        if let Some(pos) = self.allocations.iter().position(|&(o, s)| o == offset && s == size) {
+            self.allocations.remove(pos);
+            self.release_range(offset, size);
+        }
+    }
+}
+
+impl Default for SlabList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for SlabClass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
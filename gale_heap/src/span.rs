@@ -0,0 +1,172 @@
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::{align_up, PAGE_SIZE};
+
+// os dois mapas dos objetos grandes têm uma posição por página da região: cada span é uma
+// sequência contígua de páginas e o tamanho dele (em páginas) fica anotado na primeira
+// página dos spans vivos e nas duas pontas dos livres. como não cabem dois spans começando
+// na mesma página, nenhum deles enche
+
+// página de `addr` a partir de `base`, só para endereços no começo de uma página do mapa
+fn page_index<const N: usize>(base: usize, addr: usize) -> Option<usize> {
+    let offset = addr.checked_sub(base)?;
+    let page = offset / PAGE_SIZE;
+    (offset.is_multiple_of(PAGE_SIZE) && page < N).then_some(page)
+}
+
+// início -> tamanho dos spans vivos. cada posição é atômica e só muda pelas mãos de quem
+// tem o span, então dá para saber sem lock se um endereço que está sendo liberado é um span:
+// um bloco pequeno mora numa página que não é início de span enquanto ele estiver vivo
+pub struct LiveSpans<const N: usize> {
+    base: usize,
+    pages: [AtomicU16; N],
+}
+
+impl<const N: usize> LiveSpans<N> {
+    // `base` é o endereço da página 0, alinhado a página
+    pub const fn new(base: usize) -> Self {
+        assert!(N <= u16::MAX as usize, "span sizes are stored as u16 page counts");
+        LiveSpans { base, pages: [const { AtomicU16::new(0) }; N] }
+    }
+
+    // tamanho em bytes do span vivo que começa em `addr`, None se não há um
+    pub fn size(&self, addr: usize) -> Option<usize> {
+        let page = page_index::<N>(self.base, addr)?;
+        match self.pages[page].load(Ordering::Acquire) {
+            0 => None,
+            pages => Some(pages as usize * PAGE_SIZE),
+        }
+    }
+
+    // anota (ou muda o tamanho de) um span vivo
+    pub fn set(&self, start: usize, size: usize) {
+        let page = page_index::<N>(self.base, start).expect("span outside the map");
+        debug_assert!(size > 0 && size.is_multiple_of(PAGE_SIZE) && page + size / PAGE_SIZE <= N);
+        self.pages[page].store((size / PAGE_SIZE) as u16, Ordering::Release);
+    }
+
+    // tira o span vivo que começa em `addr` e devolve o tamanho dele
+    pub fn remove(&self, addr: usize) -> Option<usize> {
+        let page = page_index::<N>(self.base, addr)?;
+        match self.pages[page].swap(0, Ordering::AcqRel) {
+            0 => None,
+            pages => Some(pages as usize * PAGE_SIZE),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.pages
+            .iter()
+            .enumerate()
+            .map(|(page, pages)| (page, pages.load(Ordering::Acquire) as usize))
+            .filter(|&(_, pages)| pages != 0)
+            .map(|(page, pages)| (self.base + page * PAGE_SIZE, pages * PAGE_SIZE))
+    }
+}
+
+pub struct SpanMap<const N: usize> {
+    base: usize,
+    // início -> tamanho e fim -> tamanho dos spans livres, assim um span liberado acha os
+    // dois vizinhos sem procurar
+    free_head: [u16; N],
+    free_tail: [u16; N],
+}
+
+impl<const N: usize> SpanMap<N> {
+    // `base` é o endereço da página 0, alinhado a página
+    pub const fn new(base: usize) -> Self {
+        assert!(N <= u16::MAX as usize, "span sizes are stored as u16 page counts");
+        SpanMap {
+            base,
+            free_head: [0; N],
+            free_tail: [0; N],
+        }
+    }
+
+    fn page(&self, addr: usize) -> Option<usize> {
+        page_index::<N>(self.base, addr)
+    }
+
+    fn addr(&self, page: usize) -> usize {
+        self.base + page * PAGE_SIZE
+    }
+
+    pub fn free_spans(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.free_head
+            .iter()
+            .enumerate()
+            .filter(|&(_, &pages)| pages != 0)
+            .map(|(page, &pages)| (self.addr(page), pages as usize * PAGE_SIZE))
+    }
+
+    fn insert_free(&mut self, page: usize, pages: usize) {
+        self.free_head[page] = pages as u16;
+        self.free_tail[page + pages - 1] = pages as u16;
+    }
+
+    fn remove_free(&mut self, page: usize) -> usize {
+        let pages = self.free_head[page] as usize;
+        self.free_head[page] = 0;
+        self.free_tail[page + pages - 1] = 0;
+        pages
+    }
+
+    // first fit nos spans livres; as sobras antes e depois do alinhamento voltam a ser livres
+    pub fn take_free(&mut self, size: usize, align: usize) -> Option<usize> {
+        let mut page = 0;
+        while page < N {
+            let pages = self.free_head[page] as usize;
+            if pages == 0 {
+                page += 1;
+                continue;
+            }
+            let (start, end) = (self.addr(page), self.addr(page + pages));
+            let aligned_start = align_up(start, align);
+            if aligned_start + size <= end {
+                self.remove_free(page);
+                if aligned_start > start {
+                    self.insert_free(page, (aligned_start - start) / PAGE_SIZE);
+                }
+                if aligned_start + size < end {
+                    let tail = (aligned_start + size - self.base) / PAGE_SIZE;
+                    self.insert_free(tail, (end - aligned_start - size) / PAGE_SIZE);
+                }
+                return Some(aligned_start);
+            }
+            page += pages;
+        }
+        None
+    }
+
+    // tira `size` bytes do span livre que começa exatamente em `start`, usado para um span
+    // vivo crescer no lugar; false se não há span livre ali ou ele é pequeno demais
+    pub fn take_free_at(&mut self, start: usize, size: usize) -> bool {
+        let page = match self.page(start) {
+            Some(page) if self.free_head[page] as usize * PAGE_SIZE >= size => page,
+            _ => return false,
+        };
+        let pages = self.remove_free(page);
+        let taken = size / PAGE_SIZE;
+        if pages > taken {
+            self.insert_free(page + taken, pages - taken);
+        }
+        true
+    }
+
+    // guarda um span livre, juntando com os vizinhos livres dos dois lados
+    pub fn release(&mut self, start: usize, size: usize) {
+        let mut page = self.page(start).expect("span outside the map");
+        let mut pages = size / PAGE_SIZE;
+        debug_assert!(pages > 0 && size.is_multiple_of(PAGE_SIZE) && page + pages <= N);
+
+        if page > 0 && self.free_tail[page - 1] != 0 {
+            let previous = page - self.free_tail[page - 1] as usize;
+            pages += self.remove_free(previous);
+            page = previous;
+        }
+        if page + pages < N && self.free_head[page + pages] != 0 {
+            pages += self.remove_free(page + pages);
+        }
+        self.insert_free(page, pages);
+    }
+}
@@ -0,0 +1,307 @@
+// sequências aleatórias de alloc/free contra um modelo simples (lista de blocos vivos),
+// rodando sobre buffers comuns do host no lugar do heap do kernel
+
+use gale_heap::{
+    merge_free_blocks, FixedVec, LiveSpans, Page, PageKind, SpanMap, PAGE_SIZE, SIZE_CLASSES, SLAB_CLASSES,
+};
+
+const SEEDS: [u64; 4] = [1, 0x5eed, 0xdead_beef, 0x1234_5678_9abc];
+const STEPS: usize = 20_000;
+
+// xorshift64, o suficiente para embaralhar as operações sem depender de crates
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// uma página de verdade na memória do host, alinhada como as do heap
+struct Region {
+    buffer: Vec<u8>,
+    start: usize,
+}
+
+impl Region {
+    fn new(pages: usize) -> Self {
+        let buffer = vec![0u8; (pages + 1) * PAGE_SIZE];
+        let start = (buffer.as_ptr() as usize + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        Region { buffer, start }
+    }
+
+    fn bytes(&mut self, addr: usize, len: usize) -> &mut [u8] {
+        let offset = addr - self.buffer.as_ptr() as usize;
+        &mut self.buffer[offset..offset + len]
+    }
+}
+
+// bloco vivo do modelo: endereço, tamanho e o byte que foi escrito nele
+#[derive(Clone, Copy)]
+struct Live {
+    addr: usize,
+    size: usize,
+    fill: u8,
+}
+
+fn assert_disjoint(live: &[Live], addr: usize, size: usize) {
+    for block in live {
+        assert!(
+            addr + size <= block.addr || block.addr + block.size <= addr,
+            "[{:#x}, +{}) overlaps live block [{:#x}, +{})",
+            addr, size, block.addr, block.size
+        );
+    }
+}
+
+fn check_contents(region: &mut Region, live: &[Live]) {
+    for block in live {
+        assert!(region.bytes(block.addr, block.size).iter().all(|&b| b == block.fill));
+    }
+}
+
+#[test]
+fn merge_joins_only_adjacent_blocks() {
+    let mut free: FixedVec<(usize, usize), 8> = FixedVec::new((0, 0));
+    for block in [(0, 16), (16, 16), (64, 8), (72, 8), (100, 4)] {
+        assert!(free.push(block));
+    }
+    merge_free_blocks(&mut free);
+    assert_eq!(&*free, &[(0, 32), (64, 16), (100, 4)]);
+}
+
+#[test]
+fn large_page_matches_model() {
+    for seed in SEEDS {
+        let mut rng = Rng(seed);
+        let mut region = Region::new(1);
+        let mut page = Page::new(region.start, PageKind::Large);
+        let mut live: Vec<Live> = Vec::new();
+
+        for step in 0..STEPS {
+            match rng.below(3) {
+                0 => {
+                    let size = SIZE_CLASSES[SLAB_CLASSES - 1] + 1 + rng.below(PAGE_SIZE / 2);
+                    let align = 1 << rng.below(9);
+                    if let Some(addr) = page.alloc_large(size, align) {
+                        assert_eq!(addr % align, 0);
+                        assert!(addr >= region.start && addr + size <= region.start + PAGE_SIZE);
+                        assert_disjoint(&live, addr, size);
+                        let fill = step as u8;
+                        region.bytes(addr, size).fill(fill);
+                        live.push(Live { addr, size, fill });
+                    }
+                }
+                1 if !live.is_empty() => {
+                    let block = live.swap_remove(rng.below(live.len()));
+                    page.dealloc_large(block.addr, block.size);
+                }
+                2 if !live.is_empty() => {
+                    let i = rng.below(live.len());
+                    let block = live.remove(i);
+                    let new_size = 1 + rng.below(PAGE_SIZE / 2);
+                    if page.resize_large(block.addr, block.size, new_size) {
+                        assert!(block.addr + new_size <= region.start + PAGE_SIZE);
+                        assert_disjoint(&live, block.addr, new_size);
+                        region.bytes(block.addr, new_size).fill(block.fill);
+                        live.push(Live { size: new_size, ..block });
+                    } else {
+                        live.push(block);
+                    }
+                }
+                _ => {}
+            }
+            check_contents(&mut region, &live);
+            assert_eq!(page.allocations.len(), live.len());
+            let free: usize = page.free_blocks.iter().map(|&(_, size)| size).sum();
+            let used: usize = live.iter().map(|block| block.size).sum();
+            assert_eq!(free + used, page.current_offset);
+        }
+
+        // com tudo liberado nenhum byte pode ter se perdido no caminho
+        for block in live.drain(..) {
+            page.dealloc_large(block.addr, block.size);
+        }
+        assert_eq!(page.current_offset, 0);
+        assert!(page.free_blocks.is_empty());
+    }
+}
+
+#[test]
+fn slab_page_matches_model() {
+    for seed in SEEDS {
+        for (class, &block_size) in SIZE_CLASSES[..SLAB_CLASSES].iter().enumerate() {
+            let mut rng = Rng(seed);
+            let mut region = Region::new(1);
+            let mut page = Page::new(region.start, PageKind::Large);
+            page.init_slab(class);
+            let mut live: Vec<Live> = Vec::new();
+
+            for step in 0..STEPS / 4 {
+                if rng.below(2) == 0 {
+                    match page.alloc_slot() {
+                        Some(addr) => {
+                            assert_eq!((addr - region.start) % block_size, 0);
+                            assert!(addr + block_size <= region.start + PAGE_SIZE);
+                            assert_disjoint(&live, addr, block_size);
+                            let fill = step as u8;
+                            region.bytes(addr, block_size).fill(fill);
+                            live.push(Live { addr, size: block_size, fill });
+                        }
+                        // só acaba quando todos os slots estão entregues
+                        None => assert_eq!(live.len(), page.slab_slots()),
+                    }
+                } else if !live.is_empty() {
+                    let block = live.swap_remove(rng.below(live.len()));
+                    assert!(page.free_slot(block.addr));
+                    // o mesmo slot liberado duas vezes é recusado
+                    assert!(!page.free_slot(block.addr));
+                }
+                assert_eq!(page.slab_free, page.slab_slots() - live.len());
+                check_contents(&mut region, &live);
+            }
+        }
+    }
+}
+
+#[test]
+fn span_map_matches_model() {
+    const PAGES: usize = 64;
+
+    for seed in SEEDS {
+        let mut rng = Rng(seed);
+        // os spans nunca são tocados pelo mapa, um endereço qualquer serve de região
+        let start = 0x7f00_0000_0000;
+        let mut spans: SpanMap<PAGES> = SpanMap::new(start);
+        let live_spans: LiveSpans<PAGES> = LiveSpans::new(start);
+        spans.release(start, PAGES * PAGE_SIZE);
+        let mut live: Vec<Live> = Vec::new();
+
+        for _ in 0..STEPS {
+            match rng.below(3) {
+                0 => {
+                    let size = (1 + rng.below(8)) * PAGE_SIZE;
+                    let align = PAGE_SIZE << rng.below(3);
+                    if let Some(addr) = spans.take_free(size, align) {
+                        assert_eq!(addr % align, 0);
+                        assert!(addr >= start && addr + size <= start + PAGES * PAGE_SIZE);
+                        assert_disjoint(&live, addr, size);
+                        live_spans.set(addr, size);
+                        live.push(Live { addr, size, fill: 0 });
+                    }
+                }
+                1 if !live.is_empty() => {
+                    let block = live.swap_remove(rng.below(live.len()));
+                    assert_eq!(live_spans.remove(block.addr), Some(block.size));
+                    assert_eq!(live_spans.size(block.addr), None);
+                    spans.release(block.addr, block.size);
+                }
+                2 if !live.is_empty() => {
+                    // cresce no lugar com o span livre logo depois, se houver
+                    let i = rng.below(live.len());
+                    let extra = (1 + rng.below(4)) * PAGE_SIZE;
+                    if spans.take_free_at(live[i].addr + live[i].size, extra) {
+                        live[i].size += extra;
+                        live_spans.set(live[i].addr, live[i].size);
+                    }
+                }
+                _ => {}
+            }
+
+            // sem vizinhos livres encostados, e nenhuma página some ou aparece duas vezes
+            let free: Vec<(usize, usize)> = spans.free_spans().collect();
+            assert!(free.windows(2).all(|pair| pair[0].0 + pair[0].1 < pair[1].0));
+            let free: usize = free.iter().map(|&(_, size)| size).sum();
+            let used: usize = live.iter().map(|block| block.size).sum();
+            assert_eq!(free + used, PAGES * PAGE_SIZE);
+            assert_eq!(live_spans.iter().count(), live.len());
+            for block in &live {
+                assert_eq!(live_spans.size(block.addr), Some(block.size));
+            }
+        }
+
+        for block in live.drain(..) {
+            live_spans.remove(block.addr);
+            spans.release(block.addr, block.size);
+        }
+        assert_eq!(spans.free_spans().collect::<Vec<_>>(), [(start, PAGES * PAGE_SIZE)]);
+    }
+}
+
+#[test]
+fn every_page_can_be_its_own_span() {
+    const PAGES: usize = 512;
+    let start = 0x10_0000;
+    let mut spans: SpanMap<PAGES> = SpanMap::new(start);
+    let live: LiveSpans<PAGES> = LiveSpans::new(start);
+
+    // uma página livre a cada duas: metade do mapa em spans separados, nenhum se perde
+    for page in (0..PAGES).step_by(2) {
+        spans.release(start + page * PAGE_SIZE, PAGE_SIZE);
+    }
+    assert_eq!(spans.free_spans().count(), PAGES / 2);
+    for page in (1..PAGES).step_by(2) {
+        live.set(start + page * PAGE_SIZE, PAGE_SIZE);
+    }
+    assert_eq!(live.iter().count(), PAGES / 2);
+
+    // liberar as do meio junta tudo de novo
+    for page in (1..PAGES).step_by(2) {
+        assert_eq!(live.remove(start + page * PAGE_SIZE), Some(PAGE_SIZE));
+        spans.release(start + page * PAGE_SIZE, PAGE_SIZE);
+    }
+    assert_eq!(spans.free_spans().collect::<Vec<_>>(), [(start, PAGES * PAGE_SIZE)]);
+}
+
+#[test]
+fn span_map_coalesces_and_splits() {
+    let start = 0x10_0000;
+    let mut spans: SpanMap<16> = SpanMap::new(start);
+    spans.release(start + PAGE_SIZE, PAGE_SIZE);
+    spans.release(start + 4 * PAGE_SIZE, PAGE_SIZE);
+    // encostado num span livre ele é juntado, dos dois lados
+    spans.release(start + 5 * PAGE_SIZE, PAGE_SIZE);
+    spans.release(start + 3 * PAGE_SIZE, PAGE_SIZE);
+    assert_eq!(
+        spans.free_spans().collect::<Vec<_>>(),
+        [(start + PAGE_SIZE, PAGE_SIZE), (start + 3 * PAGE_SIZE, 3 * PAGE_SIZE)]
+    );
+    // e o buraco entre os dois fecha tudo num span só
+    spans.release(start + 2 * PAGE_SIZE, PAGE_SIZE);
+    assert_eq!(spans.free_spans().collect::<Vec<_>>(), [(start + PAGE_SIZE, 5 * PAGE_SIZE)]);
+
+    // alinhado no meio: sobra livre antes e depois
+    assert_eq!(spans.take_free(PAGE_SIZE, 4 * PAGE_SIZE), Some(start + 4 * PAGE_SIZE));
+    assert_eq!(
+        spans.free_spans().collect::<Vec<_>>(),
+        [(start + PAGE_SIZE, 3 * PAGE_SIZE), (start + 5 * PAGE_SIZE, PAGE_SIZE)]
+    );
+    // crescer só usa o span livre que começa bem no fim, e só se ele for grande o bastante
+    assert!(!spans.take_free_at(start + 5 * PAGE_SIZE, 2 * PAGE_SIZE));
+    assert!(spans.take_free_at(start + 5 * PAGE_SIZE, PAGE_SIZE));
+    assert!(!spans.take_free_at(start + 6 * PAGE_SIZE, PAGE_SIZE));
+    assert_eq!(spans.free_spans().collect::<Vec<_>>(), [(start + PAGE_SIZE, 3 * PAGE_SIZE)]);
+}
+
+#[test]
+fn only_span_starts_are_live_spans() {
+    let start = 0x10_0000;
+    let live: LiveSpans<16> = LiveSpans::new(start);
+    live.set(start + 2 * PAGE_SIZE, 3 * PAGE_SIZE);
+    assert_eq!(live.size(start + 2 * PAGE_SIZE), Some(3 * PAGE_SIZE));
+    // o meio do span, um endereço no meio de uma página e qualquer coisa fora do mapa não são
+    assert_eq!(live.size(start + 3 * PAGE_SIZE), None);
+    assert_eq!(live.size(start + 2 * PAGE_SIZE + 64), None);
+    assert_eq!(live.size(start - PAGE_SIZE), None);
+    assert_eq!(live.size(start + 16 * PAGE_SIZE), None);
+    // remover duas vezes devolve o span uma vez só
+    assert_eq!(live.remove(start + 2 * PAGE_SIZE), Some(3 * PAGE_SIZE));
+    assert_eq!(live.remove(start + 2 * PAGE_SIZE), None);
+}
@@ -0,0 +1,198 @@
+//espaços de endereçamento separados, um por processo
+//cada um tem sua própria tabela de nível 4. este kernel vive na metade de baixo (imagem,
+//heap, vmm e mapa da memória física estão todos abaixo de 0x8000_0000_0000), então a parte
+//do usuário é uma janela fixa de entradas do nível 4, [USER_START, USER_END), reservada no
+//vmm no boot; todas as outras entradas são do kernel e são copiadas da tabela do kernel,
+//apontando para as mesmas tabelas de nível 3
+//
+//o desenho de sempre é o kernel na metade de cima (entradas 256..512) e o usuário embaixo,
+//mas o bootloader 0.9 põe a imagem, a pilha de boot e o mapa da memória física na metade de
+//baixo e o HEAP_START também fica lá; por isso o que é compartilhado é "tudo fora da janela",
+//não a metade de cima
+
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::mapper::{MapToError, TranslateResult};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame,
+    Size2MiB, Size4KiB, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::memory::{BootInfoFrameAllocator, FRAME_ALLOCATOR, MAPPER};
+
+// 512 GiB por entrada do nível 4, a janela vai da entrada 32 à 127; fica abaixo do heap
+// (entrada 136) e da janela do vmm (entrada 170)
+pub const USER_START: u64 = 0x_1000_0000_0000;
+pub const USER_END: u64 = 0x_4000_0000_0000;
+
+const USER_P4_ENTRIES: core::ops::Range<usize> = (USER_START >> 39) as usize..(USER_END >> 39) as usize;
+
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    physical_memory_offset: VirtAddr,
+}
+
+fn with_frames<R>(f: impl FnOnce(&mut BootInfoFrameAllocator) -> R) -> Option<R> {
+    x86_64::instructions::interrupts::without_interrupts(|| FRAME_ALLOCATOR.lock().as_mut().map(f))
+}
+
+// o frame da tabela de nível 4 do kernel, a que MAPPER edita
+fn kernel_level_4() -> Option<(PhysFrame, VirtAddr)> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mapper = MAPPER.lock();
+        let mapper = mapper.as_ref()?;
+        let offset = mapper.phys_offset();
+        let virt = VirtAddr::from_ptr(mapper.level_4_table() as *const PageTable);
+        Some((PhysFrame::containing_address(PhysAddr::new(virt - offset)), offset))
+    })
+}
+
+impl AddressSpace {
+    //cria um espaço vazio na parte do usuário e com as mesmas entradas do kernel no resto;
+    //entradas de nível 4 que o kernel criar depois não aparecem nos espaços que já existem,
+    //por isso init_heap já cria as do heap e da janela do vmm. se o kernel tiver algo
+    //mapeado dentro da janela do usuário devolve PageAlreadyMapped com o frame da entrada
+    pub fn new() -> Result<Self, MapToError<Size4KiB>> {
+        let (kernel_frame, physical_memory_offset) = kernel_level_4().ok_or(MapToError::FrameAllocationFailed)?;
+        let kernel = unsafe { &*(physical_memory_offset + kernel_frame.start_address().as_u64()).as_ptr::<PageTable>() };
+        // conferido antes de alocar, assim o erro não deixa frame para trás
+        if let Some(entry) = USER_P4_ENTRIES.map(|index| &kernel[index]).find(|entry| !entry.is_unused()) {
+            return Err(MapToError::PageAlreadyMapped(PhysFrame::containing_address(entry.addr())));
+        }
+        let level_4_frame = with_frames(|frames| frames.allocate_frame())
+            .flatten()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+
+        let space = AddressSpace { level_4_frame, physical_memory_offset };
+        let table = unsafe { &mut *space.table_ptr(level_4_frame) };
+        table.zero();
+        for (index, entry) in kernel.iter().enumerate() {
+            if !USER_P4_ENTRIES.contains(&index) {
+                table[index] = entry.clone();
+            }
+        }
+        Ok(space)
+    }
+
+    fn table_ptr(&self, frame: PhysFrame) -> *mut PageTable {
+        (self.physical_memory_offset + frame.start_address().as_u64()).as_mut_ptr()
+    }
+
+    fn mapper(&mut self) -> OffsetPageTable<'_> {
+        unsafe { OffsetPageTable::new(&mut *self.table_ptr(self.level_4_frame), self.physical_memory_offset) }
+    }
+
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.level_4_frame
+    }
+
+    //mapeia [start, start + size) em frames novos e zerados com USER_ACCESSIBLE (que também
+    //vai para as tabelas intermediárias); o intervalo precisa estar na janela do usuário
+    pub fn map_user(&mut self, start: VirtAddr, size: u64, flags: PageTableFlags) -> Result<(), MapToError<Size4KiB>> {
+        let end = start.as_u64() + size;
+        assert!(
+            start.as_u64() >= USER_START && end <= USER_END,
+            "{:?}..{:#x} is outside the user window", start, end
+        );
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        let offset = self.physical_memory_offset;
+        let active = self.is_active();
+        let mut mapper = self.mapper();
+
+        let first = Page::<Size4KiB>::containing_address(start);
+        let last = Page::<Size4KiB>::containing_address(VirtAddr::new(end - 1));
+        for page in Page::range_inclusive(first, last) {
+            if mapper.translate_addr(page.start_address()).is_some() {
+                continue;
+            }
+            with_frames(|frames| -> Result<(), MapToError<Size4KiB>> {
+                let frame = frames.allocate_frame().ok_or(MapToError::FrameAllocationFailed)?;
+                // zerado pelo mapa da memória física, a página pode nem estar ativa agora
+                unsafe {
+                    core::ptr::write_bytes((offset + frame.start_address().as_u64()).as_mut_ptr::<u8>(), 0, Size4KiB::SIZE as usize);
+                    let flush = mapper.map_to(page, frame, flags, frames)?;
+                    // só o espaço ativo pode ter entradas velhas no TLB
+                    if active {
+                        flush.flush();
+                    } else {
+                        flush.ignore();
+                    }
+                }
+                Ok(())
+            })
+            .ok_or(MapToError::FrameAllocationFailed)??;
+        }
+        Ok(())
+    }
+
+    pub fn translate(&mut self, addr: VirtAddr) -> Option<(PhysAddr, PageTableFlags)> {
+        match self.mapper().translate(addr) {
+            TranslateResult::Mapped { frame, offset, flags } => Some((frame.start_address() + offset, flags)),
+            _ => None,
+        }
+    }
+
+    //troca o CR3 para este espaço
+    //
+    //unsafe: o código e a pilha que estão rodando precisam ser do kernel (ou estar mapeados aqui)
+    pub unsafe fn activate(&self) {
+        let (_, flags) = Cr3::read();
+        Cr3::write(self.level_4_frame, flags);
+    }
+}
+
+//volta o CR3 para a tabela do kernel
+pub fn activate_kernel() {
+    if let Some((frame, _)) = kernel_level_4() {
+        let (_, flags) = Cr3::read();
+        unsafe { Cr3::write(frame, flags) };
+    }
+}
+
+// devolve as tabelas abaixo de `table` (nível `level`) e os frames mapeados por elas
+unsafe fn free_table(
+    frames: &mut BootInfoFrameAllocator,
+    offset: VirtAddr,
+    table: &PageTable,
+    level: usize,
+) {
+    for entry in table.iter().filter(|entry| entry.flags().contains(PageTableFlags::PRESENT)) {
+        if level == 1 {
+            frames.deallocate_frame(PhysFrame::<Size4KiB>::containing_address(entry.addr()));
+        } else if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            frames.deallocate_frame(PhysFrame::<Size2MiB>::containing_address(entry.addr()));
+        } else {
+            let next = &*(offset + entry.addr().as_u64()).as_ptr::<PageTable>();
+            free_table(frames, offset, next, level - 1);
+            frames.deallocate_frame(PhysFrame::<Size4KiB>::containing_address(entry.addr()));
+        }
+    }
+}
+
+impl Drop for AddressSpace {
+    //libera a parte do usuário (tabelas e frames) e a própria tabela de nível 4; as
+    //entradas do kernel são compartilhadas e ficam como estão
+    fn drop(&mut self) {
+        if self.is_active() {
+            activate_kernel();
+        }
+
+        let offset = self.physical_memory_offset;
+        let table = unsafe { &*self.table_ptr(self.level_4_frame) };
+        with_frames(|frames| unsafe {
+            for index in USER_P4_ENTRIES {
+                let entry = &table[index];
+                if entry.flags().contains(PageTableFlags::PRESENT) {
+                    let level_3 = &*(offset + entry.addr().as_u64()).as_ptr::<PageTable>();
+                    free_table(frames, offset, level_3, 3);
+                    frames.deallocate_frame(PhysFrame::<Size4KiB>::containing_address(entry.addr()));
+                }
+            }
+            frames.deallocate_frame(self.level_4_frame);
+        });
+    }
+}
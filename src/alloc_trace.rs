@@ -0,0 +1,167 @@
+//rastreamento opcional das alocações (feature `alloc-trace`)
+//os eventos vão para um buffer circular de tamanho fixo sem locks, então gravar
+//um evento de dentro do alocador nunca aloca nem pega o lock do vga ou da serial;
+//depois o buffer pode ser despejado na serial com `dump`
+
+#[cfg(feature = "alloc-trace")]
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use crate::serial_println;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EventKind {
+    Alloc = 1,
+    Dealloc = 2,
+    Realloc = 3,
+    // o heap mapeou mais páginas (addr = início, size = bytes)
+    Grow = 4,
+    // o pedido não pôde ser atendido
+    Fail = 5,
+}
+
+impl EventKind {
+    #[cfg(feature = "alloc-trace")]
+    fn from_u8(value: u8) -> Option<EventKind> {
+        match value {
+            1 => Some(EventKind::Alloc),
+            2 => Some(EventKind::Dealloc),
+            3 => Some(EventKind::Realloc),
+            4 => Some(EventKind::Grow),
+            5 => Some(EventKind::Fail),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub addr: usize,
+    pub size: usize,
+    pub align: usize,
+    // endereço antigo num realloc, 0 nos outros eventos
+    pub old_addr: usize,
+}
+
+#[cfg(feature = "alloc-trace")]
+const TRACE_CAPACITY: usize = 256;
+
+// cada posição é publicada gravando `seq` por último; quem lê confere `seq`
+// antes e depois de copiar os campos para descartar posições sendo sobrescritas
+#[cfg(feature = "alloc-trace")]
+struct Slot {
+    seq: AtomicUsize,
+    kind: AtomicU8,
+    addr: AtomicUsize,
+    size: AtomicUsize,
+    align: AtomicUsize,
+    old_addr: AtomicUsize,
+}
+
+#[cfg(feature = "alloc-trace")]
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            seq: AtomicUsize::new(0),
+            kind: AtomicU8::new(0),
+            addr: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+            align: AtomicUsize::new(0),
+            old_addr: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[cfg(feature = "alloc-trace")]
+struct TraceBuffer {
+    next: AtomicUsize,
+    slots: [Slot; TRACE_CAPACITY],
+}
+
+#[cfg(feature = "alloc-trace")]
+static TRACE: TraceBuffer = TraceBuffer {
+    next: AtomicUsize::new(0),
+    slots: [const { Slot::new() }; TRACE_CAPACITY],
+};
+
+#[inline]
+pub fn record(event: Event) {
+    #[cfg(feature = "alloc-trace")]
+    {
+        let index = TRACE.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &TRACE.slots[index % TRACE_CAPACITY];
+
+        // seq 0 marca a posição como incompleta enquanto os campos mudam
+        slot.seq.store(0, Ordering::Release);
+        slot.kind.store(event.kind as u8, Ordering::Relaxed);
+        slot.addr.store(event.addr, Ordering::Relaxed);
+        slot.size.store(event.size, Ordering::Relaxed);
+        slot.align.store(event.align, Ordering::Relaxed);
+        slot.old_addr.store(event.old_addr, Ordering::Relaxed);
+        slot.seq.store(index + 1, Ordering::Release);
+    }
+
+    #[cfg(not(feature = "alloc-trace"))]
+    let _ = event;
+}
+
+// chama `f` com os eventos ainda guardados, do mais antigo para o mais novo
+pub fn for_each_event(mut f: impl FnMut(usize, Event)) {
+    #[cfg(feature = "alloc-trace")]
+    {
+        let end = TRACE.next.load(Ordering::Acquire);
+        let start = end.saturating_sub(TRACE_CAPACITY);
+        for index in start..end {
+            let slot = &TRACE.slots[index % TRACE_CAPACITY];
+            if slot.seq.load(Ordering::Acquire) != index + 1 {
+                continue;
+            }
+            let kind = slot.kind.load(Ordering::Relaxed);
+            let event = Event {
+                kind: match EventKind::from_u8(kind) {
+                    Some(kind) => kind,
+                    None => continue,
+                },
+                addr: slot.addr.load(Ordering::Relaxed),
+                size: slot.size.load(Ordering::Relaxed),
+                align: slot.align.load(Ordering::Relaxed),
+                old_addr: slot.old_addr.load(Ordering::Relaxed),
+            };
+            if slot.seq.load(Ordering::Acquire) != index + 1 {
+                continue;
+            }
+            f(index, event);
+        }
+    }
+
+    #[cfg(not(feature = "alloc-trace"))]
+    let _ = &mut f;
+}
+
+// despeja os eventos guardados na serial
+pub fn dump() {
+    if !cfg!(feature = "alloc-trace") {
+        serial_println!("alloc trace disabled (build with --features alloc-trace)");
+        return;
+    }
+
+    serial_println!("alloc trace:");
+    for_each_event(|index, event| {
+        serial_println!(
+            "{:>6} {:<7?} addr={:#x} size={} align={} old={:#x}",
+            index, event.kind, event.addr, event.size, event.align, event.old_addr
+        );
+    });
+}
+
+#[cfg(feature = "alloc-trace")]
+#[test_case]
+fn test_recorded_events_are_read_back() {
+    let event = Event { kind: EventKind::Grow, addr: 0x1000, size: 4096, align: 0, old_addr: 0 };
+    record(event);
+
+    let mut last = None;
+    for_each_event(|_, e| last = Some(e));
+    assert_eq!(last, Some(event));
+}
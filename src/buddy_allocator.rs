@@ -0,0 +1,289 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::alloc_trace::{self, Event, EventKind};
+use crate::combined_allocator::{HeapStats, HEAP_MAX_SIZE, HEAP_SIZE, HEAP_START, SIZE_CLASSES};
+use crate::memory;
+use crate::oom;
+
+const MIN_ORDER: usize = 4; // menor bloco: 16 bytes, cabe o ponteiro da lista livre
+const MAX_ORDER: usize = HEAP_MAX_SIZE.trailing_zeros() as usize; // um bloco do tamanho da janela inteira
+const ORDERS: usize = MAX_ORDER - MIN_ORDER + 1;
+const HEAP_GROWTH: usize = 64 * 1024; // quanto o heap cresce de cada vez
+
+// os blocos são alinhados a partir de HEAP_START, a janela precisa ser uma potência de dois
+const _: () = assert!(HEAP_MAX_SIZE.is_power_of_two());
+
+// alocador buddy sobre a mesma janela do heap que o CombinedAllocator, para comparar os dois;
+// as listas livres ficam dentro dos próprios blocos livres, então nada aqui usa o heap
+pub struct BuddyAllocator {
+    ready: AtomicBool,
+    heap: Mutex<BuddyHeap>,
+    // bytes em blocos entregues (já arredondados para a ordem) e o máximo visto
+    allocated: AtomicUsize,
+    peak: AtomicUsize,
+    // blocos vivos por classe de SIZE_CLASSES, para o relatório ficar igual ao do outro alocador
+    class_counts: [AtomicUsize; SIZE_CLASSES.len()],
+}
+
+struct BuddyHeap {
+    // cabeça da lista livre de cada ordem, 0 = vazia; cada bloco livre guarda o próximo
+    free_lists: [usize; ORDERS],
+    // fim da parte do heap que já está mapeada
+    heap_end: usize,
+}
+
+// ordem do bloco que comporta o tamanho e o alinhamento pedidos
+fn order_for(size: usize, align: usize) -> usize {
+    let block = size.max(align).max(1 << MIN_ORDER).next_power_of_two();
+    block.trailing_zeros() as usize - MIN_ORDER
+}
+
+fn block_size(order: usize) -> usize {
+    1 << (order + MIN_ORDER)
+}
+
+fn size_class(order: usize) -> Option<usize> {
+    SIZE_CLASSES.iter().position(|&class| class >= block_size(order))
+}
+
+impl BuddyHeap {
+    const fn new() -> Self {
+        BuddyHeap {
+            free_lists: [0; ORDERS],
+            heap_end: HEAP_START,
+        }
+    }
+
+    fn push(&mut self, order: usize, addr: usize) {
+        unsafe { *(addr as *mut usize) = self.free_lists[order] };
+        self.free_lists[order] = addr;
+    }
+
+    fn pop(&mut self, order: usize) -> Option<usize> {
+        let addr = self.free_lists[order];
+        if addr == 0 {
+            return None;
+        }
+        self.free_lists[order] = unsafe { *(addr as *const usize) };
+        Some(addr)
+    }
+
+    // tira `addr` da lista da ordem, se ele estiver lá
+    fn remove(&mut self, order: usize, addr: usize) -> bool {
+        let mut link = &mut self.free_lists[order] as *mut usize;
+        unsafe {
+            while *link != 0 {
+                if *link == addr {
+                    *link = *(addr as *const usize);
+                    return true;
+                }
+                link = *link as *mut usize;
+            }
+        }
+        false
+    }
+
+    fn alloc(&mut self, order: usize) -> Option<usize> {
+        let from = (order..ORDERS).find(|&o| self.free_lists[o] != 0)?;
+        let addr = self.pop(from)?;
+
+        // divide o bloco ao meio até chegar na ordem pedida, devolvendo as metades de cima
+        for o in (order..from).rev() {
+            self.push(o, addr + block_size(o));
+        }
+        Some(addr)
+    }
+
+    // devolve o bloco juntando com o buddy enquanto ele também estiver livre
+    fn free(&mut self, addr: usize, order: usize) {
+        let mut offset = addr - HEAP_START;
+        let mut order = order;
+        while order + 1 < ORDERS {
+            let buddy = offset ^ block_size(order);
+            if !self.remove(order, HEAP_START + buddy) {
+                break;
+            }
+            offset = offset.min(buddy);
+            order += 1;
+        }
+        self.push(order, HEAP_START + offset);
+    }
+
+    // entrega [start, end) para as listas livres como os maiores blocos alinhados possíveis
+    fn add_region(&mut self, start: usize, end: usize) {
+        let mut offset = start - HEAP_START;
+        let end = end - HEAP_START;
+        while offset + block_size(0) <= end {
+            let mut order = 0;
+            while order + 1 < ORDERS
+                && offset % block_size(order + 1) == 0
+                && offset + block_size(order + 1) <= end
+            {
+                order += 1;
+            }
+            self.free(HEAP_START + offset, order);
+            offset += block_size(order);
+        }
+    }
+
+    // mapeia o suficiente para caber um bloco alinhado da ordem pedida
+    fn grow(&mut self, order: usize) -> bool {
+        let size = block_size(order);
+        let offset = self.heap_end - HEAP_START;
+        let target = ((offset + size - 1) & !(size - 1)) + size;
+        let target = target.max(offset + HEAP_GROWTH).min(HEAP_MAX_SIZE);
+        if target <= offset {
+            return false;
+        }
+
+        let new_end = HEAP_START + target;
+        if memory::map_heap_range(self.heap_end, new_end - self.heap_end).is_err() {
+            return false;
+        }
+        let old_end = self.heap_end;
+        self.heap_end = new_end;
+        self.add_region(old_end, new_end);
+        alloc_trace::record(Event { kind: EventKind::Grow, addr: old_end, size: new_end - old_end, align: 0, old_addr: 0 });
+        true
+    }
+}
+
+impl BuddyAllocator {
+    const fn new() -> Self {
+        BuddyAllocator {
+            ready: AtomicBool::new(false),
+            heap: Mutex::new(BuddyHeap::new()),
+            allocated: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            class_counts: [const { AtomicUsize::new(0) }; SIZE_CLASSES.len()],
+        }
+    }
+
+    // entrega o heap inicial para as listas livres, só deve ser chamado depois que
+    // HEAP_START até HEAP_START + HEAP_SIZE estiver mapeado (memory::init_heap)
+    pub unsafe fn init(&self) {
+        let mut heap = self.heap.lock();
+        heap.heap_end = HEAP_START + HEAP_SIZE;
+        heap.add_region(HEAP_START, HEAP_START + HEAP_SIZE);
+        self.ready.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    // bloco da ordem pedida, crescendo o heap se preciso; 0 se não há memória
+    fn alloc_order(&self, order: usize) -> usize {
+        // um handler que aloca não pode interromper quem está com o lock
+        without_interrupts(|| {
+            let mut heap = self.heap.lock();
+            loop {
+                if let Some(addr) = heap.alloc(order) {
+                    break addr;
+                }
+                if !heap.grow(order) {
+                    break 0;
+                }
+            }
+        })
+    }
+
+    fn count_alloc(&self, order: usize) {
+        let allocated = self.allocated.fetch_add(block_size(order), Ordering::SeqCst) + block_size(order);
+        self.peak.fetch_max(allocated, Ordering::SeqCst);
+        if let Some(class) = size_class(order) {
+            self.class_counts[class].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn count_dealloc(&self, order: usize) {
+        self.allocated.fetch_sub(block_size(order), Ordering::SeqCst);
+        if let Some(class) = size_class(order) {
+            self.class_counts[class].fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let heap = self.heap.lock();
+        let heap_size = heap.heap_end - HEAP_START;
+        let bytes_allocated = self.allocated.load(Ordering::SeqCst);
+        let bytes_free = heap_size.saturating_sub(bytes_allocated);
+        // blocos livres nunca têm buddy livre, então o maior é o da maior ordem não vazia
+        let largest_free_block = (0..ORDERS).rev()
+            .find(|&order| heap.free_lists[order] != 0)
+            .map_or(0, block_size);
+        let fragmentation_percent = if bytes_free == 0 {
+            0
+        } else {
+            100 - largest_free_block.min(bytes_free) * 100 / bytes_free
+        };
+
+        HeapStats {
+            heap_size,
+            max_size: HEAP_MAX_SIZE,
+            bytes_allocated,
+            bytes_free,
+            peak_allocated: self.peak.load(Ordering::SeqCst),
+            pages: heap_size / 4096,
+            class_counts: core::array::from_fn(|i| self.class_counts[i].load(Ordering::SeqCst)),
+            largest_free_block,
+            fragmentation_percent,
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for BuddyAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // os blocos só são alinhados em relação a HEAP_START
+        if !self.is_ready() || HEAP_START % layout.align() != 0 {
+            return null_mut();
+        }
+
+        let order = order_for(layout.size(), layout.align());
+        if order >= ORDERS {
+            return null_mut();
+        }
+
+        let mut addr = self.alloc_order(order);
+        if addr == 0 {
+            addr = oom::handle(layout, || self.alloc_order(order) as *mut u8) as usize;
+        }
+
+        if addr != 0 {
+            self.count_alloc(order);
+        }
+
+        let kind = if addr == 0 { EventKind::Fail } else { EventKind::Alloc };
+        alloc_trace::record(Event { kind, addr, size: layout.size(), align: layout.align(), old_addr: 0 });
+        addr as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        alloc_trace::record(Event { kind: EventKind::Dealloc, addr: ptr as usize, size: layout.size(), align: layout.align(), old_addr: 0 });
+        let order = order_for(layout.size(), layout.align());
+        without_interrupts(|| self.heap.lock().free(ptr as usize, order));
+        self.count_dealloc(order);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // enquanto o novo tamanho cabe no mesmo bloco nada precisa mudar
+        if order_for(new_size, layout.align()) == order_for(layout.size(), layout.align()) {
+            return ptr;
+        }
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg_attr(all(feature = "allocator-buddy", not(feature = "alloc-leak")), global_allocator)]
+pub static ALLOCATOR: BuddyAllocator = BuddyAllocator::new();
@@ -1,138 +1,151 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::null_mut;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
 
-const HEAP_START: usize = 0x_4444_4444_0000;
-const HEAP_SIZE: usize = 200 * 1024; // 200 KiB
-const PAGE_SIZE: usize = 4096; // 4 KiB pages
-const SMALL_BLOCK_SIZE: usize = 256; // Threshold for small blocks
-const MAX_CACHEABLE_SIZE: usize = 1024; // Defina o tamanho máximo para os blocos de memória alocados que podem ser reutilizados
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+pub const HEAP_SIZE: usize = 200 * 1024; // 200 KiB liberados no boot, os frames vêm no primeiro acesso
+pub const HEAP_MAX_SIZE: usize = 8 * 1024 * 1024; // 8 MiB de janela virtual reservada para o heap
+const HEAP_GROWTH: usize = 16 * PAGE_SIZE; // quanto o heap cresce de cada vez
+const MAX_PAGES: usize = HEAP_MAX_SIZE / PAGE_SIZE; // registros de página que cabem na janela de PageList
+const MAGAZINE_SIZE: usize = 16; // blocos em cada magazine
+const DEPOT_DEPTH: usize = 8; // magazines cheios guardados por classe no depósito
 
-extern crate alloc;
-use alloc::vec::Vec;
+// as páginas, slabs e spans moram no gale_heap, que não sabe onde fica o heap
+pub use gale_heap::SIZE_CLASSES;
+use gale_heap::{
+    align_up, block_size, is_large_object, size_class, FixedVec, LiveSpans, Page, PageKind, SlabClass, SpanMap,
+    NO_PAGE, PAGE_SIZE, SLAB_CLASSES,
+};
 
-use crate::println;
+use crate::alloc_trace::{self, Event, EventKind};
+use crate::cpu::{self, MAX_CPUS};
+use crate::memory;
+use crate::oom;
+use crate::vmm::{self, RegionKind};
 
 pub struct CombinedAllocator {
     _heap_start: usize,
-    heap_end: usize,
+    // fim da parte do heap que já está mapeada
+    heap_end: AtomicUsize,
     heap_current: AtomicUsize,
-    pages: Mutex<Vec<Page>>,
-    cache: Mutex<Vec<usize>>,
+    // teto até onde o heap pode crescer
+    max_size: AtomicUsize,
+    ready: AtomicBool,
+    pages: Mutex<PageList>,
+    // listas de páginas de cada classe do slab, só mexidas com o lock de pages pego
+    slabs: Mutex<[SlabClass; SLAB_CLASSES]>,
+    // uma posição por página da janela do heap, então qualquer número de spans cabe; os
+    // vivos são lidos sem lock por dealloc e realloc, o lock é só dos livres
+    live_spans: LiveSpans<MAX_PAGES>,
+    spans: Mutex<SpanMap<MAX_PAGES>>,
+    // blocos liberados por classe de tamanho, prontos para serem reaproveitados; cada
+    // cpu tem os seus magazines e só passa pelo depósito a cada magazine inteiro
+    cpus: [Mutex<CpuCache>; MAX_CPUS],
+    depot: Mutex<[FixedVec<Magazine, DEPOT_DEPTH>; SIZE_CLASSES.len()]>,
+    // bytes entregues e ainda não liberados, contando o arredondamento de cada bloco
+    allocated: AtomicUsize,
+    peak: AtomicUsize,
+    // blocos vivos de cada classe de SIZE_CLASSES
+    class_counts: [AtomicUsize; SIZE_CLASSES.len()],
 }
 
-struct Page {
-    start: usize,
-    current_offset: AtomicUsize,
-    allocations: Mutex<Vec<(usize, usize)>>,
-    free_blocks: Mutex<Vec<(usize, usize)>>,
-    small_block_bitmap: Mutex<u128>, // Using 128 bits for small blocks (256 / 2)
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    // bytes do heap já mapeados e o teto até onde ele pode crescer
+    pub heap_size: usize,
+    pub max_size: usize,
+    pub bytes_allocated: usize,
+    pub bytes_free: usize,
+    pub peak_allocated: usize,
+    // páginas do heap em uso (slabs, páginas de blocos grandes e spans)
+    pub pages: usize,
+    pub class_counts: [usize; SIZE_CLASSES.len()],
+    pub largest_free_block: usize,
+    // quanto do espaço livre não está no maior bloco livre, em porcentagem
+    pub fragmentation_percent: usize,
 }
 
-impl Page {
-    fn new(start: usize) -> Self {
-        Page {
-            start,
-            current_offset: AtomicUsize::new(0),
-            allocations: Mutex::new(Vec::new()),
-            free_blocks: Mutex::new(Vec::new()),
-            small_block_bitmap: Mutex::new(0),
-        }
-    }
-
-    fn compact_small_block_bitmap(bitmap: &mut u128) {
-        let mut new_bitmap = 0;
-        let mut bit_index = 0;
-    
-        for i in 0..128 {
-            if (*bitmap & (1 << i)) != 0 {
-                new_bitmap |= 1 << bit_index;
-                bit_index += 1;
-            }
+type Magazine = FixedVec<usize, MAGAZINE_SIZE>;
+
+// os registros de página (uns 650 bytes cada) num intervalo do vmm do tamanho de MAX_PAGES
+// registros, mapeado só conforme a lista cresce; um FixedVec estático ocuparia 1,3 MB de
+// .bss mesmo com o heap pequeno. vazia e sem espaço até init reservar a janela
+struct PageList {
+    base: usize,
+    // bytes já mapeados a partir de base
+    mapped: usize,
+    len: usize,
+}
+
+impl PageList {
+    const RESERVED: usize = MAX_PAGES * core::mem::size_of::<Page>();
+
+    const fn new() -> Self {
+        PageList { base: 0, mapped: 0, len: 0 }
+    }
+
+    // garante espaço mapeado para mais um registro; false com a janela cheia ou sem frames
+    fn reserve_one(&mut self) -> bool {
+        let needed = (self.len + 1) * core::mem::size_of::<Page>();
+        if self.base == 0 || needed > Self::RESERVED {
+            return false;
         }
-    
-        *bitmap = new_bitmap;
-    }    
-
-    fn alloc_small(&self, _size: usize) -> Option<usize> {
-        let mut bitmap = self.small_block_bitmap.lock();
-        let mut block_index = 0;
-        let mut bit = 1;
-        while bit != 0 {
-            if (*bitmap & bit) == 0 {
-                *bitmap |= bit;
-                return Some(self.start + block_index * SMALL_BLOCK_SIZE);
-            }
-            block_index += 1;
-            bit <<= 1;
-        }
-        None
-    }    
-
-    fn dealloc_small(&self, ptr: usize) {
-        let offset = ptr - self.start;
-        let block_index = offset / SMALL_BLOCK_SIZE;
-        let bit = 1 << block_index;
-        let mut bitmap = self.small_block_bitmap.lock();
-        *bitmap &= !bit;
-        Self::compact_small_block_bitmap(&mut *bitmap);
-    }
-
-    fn alloc_large(&self, size: usize, align: usize) -> Option<usize> {
-        println!("alloc_large");
-        let mut free_blocks = self.free_blocks.lock();
-        for (i, &(offset, block_size)) in free_blocks.iter().enumerate() {
-            let aligned_offset = (offset + align - 1) & !(align - 1);
-            if aligned_offset + size <= offset + block_size {
-                free_blocks.remove(i);
-                if aligned_offset > offset {
-                    free_blocks.push((offset, aligned_offset - offset));
-                }
-                if aligned_offset + size < offset + block_size {
-                    free_blocks.push((aligned_offset + size, offset + block_size - (aligned_offset + size)));
-                }
-                let mut allocations = self.allocations.lock();
-                allocations.push((aligned_offset, size));
-                println!("{}, {}", self.start, aligned_offset);
-                return Some(self.start + aligned_offset);
+        if needed > self.mapped {
+            let grow = align_up(needed - self.mapped, PAGE_SIZE);
+            let start = VirtAddr::new((self.base + self.mapped) as u64);
+            if vmm::map_range(start, grow as u64, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE).is_err() {
+                return false;
             }
+            self.mapped += grow;
         }
+        true
+    }
 
-        let current_offset = self.current_offset.load(Ordering::SeqCst);
-        let aligned_offset = (current_offset + align - 1) & !(align - 1);
-        if aligned_offset + size <= PAGE_SIZE {
-            self.current_offset.store(aligned_offset + size, Ordering::SeqCst);
-            let mut allocations = self.allocations.lock();
-            allocations.push((aligned_offset, size));
-            println!("{}, {}", self.start, aligned_offset);
-            Some(self.start + aligned_offset)
-        } else {
-            println!("none");
-            None
+    // só depois de reserve_one
+    fn push(&mut self, page: Page) {
+        debug_assert!((self.len + 1) * core::mem::size_of::<Page>() <= self.mapped);
+        unsafe { (self.base as *mut Page).add(self.len).write(page) };
+        self.len += 1;
+    }
+}
+
+impl core::ops::Deref for PageList {
+    type Target = [Page];
+
+    fn deref(&self) -> &[Page] {
+        if self.len == 0 {
+            return &[];
         }
+        unsafe { core::slice::from_raw_parts(self.base as *const Page, self.len) }
     }
+}
 
-    fn dealloc_large(&self, ptr: usize, size: usize) {
-        let mut allocations = self.allocations.lock();
-        if let Some(pos) = allocations.iter().position(|&(offset, s)| offset == ptr - self.start && s == size) {
-            allocations.remove(pos);
-            let mut free_blocks = self.free_blocks.lock();
-            free_blocks.push((ptr - self.start, size));
-            free_blocks.sort_unstable_by_key(|&(offset, _)| offset);
-            self.merge_free_blocks(&mut free_blocks);
+impl core::ops::DerefMut for PageList {
+    fn deref_mut(&mut self) -> &mut [Page] {
+        if self.len == 0 {
+            return &mut [];
         }
+        unsafe { core::slice::from_raw_parts_mut(self.base as *mut Page, self.len) }
     }
+}
 
-    fn merge_free_blocks(&self, free_blocks: &mut Vec<(usize, usize)>) {
-        let mut i = 0;
-        while i < free_blocks.len() - 1 {
-            if free_blocks[i].0 + free_blocks[i].1 == free_blocks[i + 1].0 {
-                free_blocks[i].1 += free_blocks[i + 1].1;
-                free_blocks.remove(i + 1);
-            } else {
-                i += 1;
-            }
+// dois magazines por classe: alloc tira do `loaded` e free devolve nele; o `previous`
+// evita ir ao depósito quando alloc e free se alternam bem na borda de um magazine
+#[derive(Clone, Copy)]
+struct CpuCache {
+    loaded: [Magazine; SIZE_CLASSES.len()],
+    previous: [Magazine; SIZE_CLASSES.len()],
+}
+
+impl CpuCache {
+    const fn new() -> Self {
+        CpuCache {
+            loaded: [FixedVec::new(0); SIZE_CLASSES.len()],
+            previous: [FixedVec::new(0); SIZE_CLASSES.len()],
         }
     }
 }
@@ -141,116 +154,571 @@ impl CombinedAllocator {
     const fn new(_heap_start: usize, heap_size: usize) -> Self {
         CombinedAllocator {
             _heap_start,
-            heap_end: _heap_start + heap_size,
+            heap_end: AtomicUsize::new(_heap_start + heap_size),
             heap_current: AtomicUsize::new(_heap_start),
-            pages: Mutex::new(Vec::new()),
-            cache: Mutex::new(Vec::new()), // Inicializa o cache com Arc<Mutex<Vec<usize>>>
+            max_size: AtomicUsize::new(HEAP_MAX_SIZE),
+            ready: AtomicBool::new(false),
+            pages: Mutex::new(PageList::new()),
+            slabs: Mutex::new([SlabClass::new(); SLAB_CLASSES]),
+            live_spans: LiveSpans::new(_heap_start),
+            spans: Mutex::new(SpanMap::new(_heap_start)),
+            cpus: [const { Mutex::new(CpuCache::new()) }; MAX_CPUS],
+            depot: Mutex::new([FixedVec::new(FixedVec::new(0)); SIZE_CLASSES.len()]),
+            allocated: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            class_counts: [const { AtomicUsize::new(0) }; SIZE_CLASSES.len()],
         }
     }
 
-    fn allocate_page(&self) -> Option<*mut Page> {
-        println!("-3");
+    // marca o heap como pronto, só deve ser chamado depois que todas as páginas
+    // de HEAP_START até HEAP_START + HEAP_SIZE estiverem mapeadas (memory::init_heap);
+    // reserva no vmm a janela dos registros de página, sem ela o heap só tem spans
+    pub unsafe fn init(&self) {
+        if let Ok(base) = vmm::allocate(PageList::RESERVED as u64, PAGE_SIZE as u64, RegionKind::Heap, "heap pages") {
+            self.pages.lock().base = base.as_u64() as usize;
+        }
+        self.ready.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    // define até quanto o heap pode crescer, limitado à janela de HEAP_MAX_SIZE
+    pub fn set_max_size(&self, size: usize) {
+        self.max_size.store(size.min(HEAP_MAX_SIZE), Ordering::SeqCst);
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size.load(Ordering::SeqCst)
+    }
+
+    // pede ao mapeador pelo menos `needed` bytes a mais (de HEAP_GROWTH em HEAP_GROWTH), até o
+    // teto configurado; um pedido grande sai de uma vez e pode ganhar páginas de 2 MiB
+    fn grow(&self, needed: usize) -> bool {
+        let heap_end = self.heap_end.load(Ordering::SeqCst);
+        let limit = self._heap_start + self.max_size();
+        let size = align_up(needed.max(1), HEAP_GROWTH).min(limit.saturating_sub(heap_end));
+        if size == 0 {
+            return false;
+        }
+
+        if memory::map_heap_range(heap_end, size).is_err() {
+            return false;
+        }
+        self.heap_end.store(heap_end + size, Ordering::SeqCst);
+        alloc_trace::record(Event { kind: EventKind::Grow, addr: heap_end, size, align: PAGE_SIZE, old_addr: 0 });
+        true
+    }
+
+    // reserva `count` páginas contíguas no fim do heap, crescendo quantas vezes
+    // for preciso; deve ser chamado com o lock de pages pego, que protege heap_current
+    fn reserve_pages(&self, count: usize, align: usize) -> Option<usize> {
         let heap_current = self.heap_current.load(Ordering::SeqCst);
-        println!("-2");
-        if heap_current + PAGE_SIZE <= self.heap_end {
-            println!("-1");
-            let mut pages = self.pages.lock();
-            let page = Page::new(heap_current);
-            println!("0, pages.len() => {}, heap_current => {}, heap_i + page => {}, heap_end => {}", pages.len(), heap_current, heap_current + PAGE_SIZE, self.heap_end);
-            pages.push(page);
-            println!("00");
-            self.heap_current.store(heap_current + PAGE_SIZE, Ordering::SeqCst);
-            println!("1");
-            Some(pages.last_mut().unwrap() as *mut _)
-        } else {
-            println!("2");
-            None
+        let start = align_up(heap_current, align.max(PAGE_SIZE));
+        let end = start + count * PAGE_SIZE;
+        while end > self.heap_end.load(Ordering::SeqCst) {
+            if !self.grow(end - self.heap_end.load(Ordering::SeqCst)) {
+                return None;
+            }
         }
+        self.heap_current.store(end, Ordering::SeqCst);
+
+        // as páginas puladas pelo alinhamento ficam disponíveis para outros spans
+        if start > heap_current {
+            self.spans.lock().release(heap_current, start - heap_current);
+        }
+        Some(start)
     }
 
-    pub unsafe fn alloc(&self, size: usize, align: usize) -> *mut u8 {
-        let mut cache = self.cache.lock();
-        if let Some(addr) = cache.pop() {
-            println!("chegou no cache");
-            return addr as *mut u8;
-        }
-        if size <= SMALL_BLOCK_SIZE {
-            println!("ta no small");
-            let pages = self.pages.lock();
-            for page in pages.iter() {
-                if let Some(addr) = page.alloc_small(size) {
-                    println!("chegou no aloc small");
-                    return addr as *mut u8;
-                }
+    fn allocate_page<'a>(&self, pages: &'a mut PageList, kind: PageKind) -> Option<&'a mut Page> {
+        if !pages.reserve_one() {
+            return None;
+        }
+        let heap_current = self.reserve_pages(1, PAGE_SIZE)?;
+
+        let page = Page::new(heap_current, kind);
+        pages.push(page);
+        pages.last_mut()
+    }
+
+    // caminho dos objetos grandes: uma sequência de páginas inteiras, reaproveitando
+    // spans livres antes de avançar o fim do heap; páginas novas já vêm zeradas
+    // (do page fault ou de memory::map_heap_range), então só spans reaproveitados precisam ser zerados
+    fn alloc_span(&self, size: usize, align: usize, zeroed: bool) -> *mut u8 {
+        let size = align_up(size, PAGE_SIZE);
+        let align = align.max(PAGE_SIZE);
+
+        // o lock de pages protege heap_current dentro de reserve_pages
+        let _pages = self.pages.lock();
+        let reused = self.spans.lock().take_free(size, align);
+        let start = match reused {
+            Some(start) => start,
+            None => match self.reserve_pages(size / PAGE_SIZE, align) {
+                Some(start) => start,
+                None => return null_mut(),
+            },
+        };
+
+        self.live_spans.set(start, size);
+
+        if zeroed && reused.is_some() {
+            unsafe { core::ptr::write_bytes(start as *mut u8, 0, size) };
+        }
+        start as *mut u8
+    }
+
+    // muda o tamanho do span que começa em `addr` sem movê-lo, usando o span livre
+    // logo depois dele ou o fim do heap; None se `addr` não é um span, sem pegar lock nenhum
+    fn resize_span(&self, addr: usize, new_size: usize) -> Option<bool> {
+        let size = self.live_spans.size(addr)?;
+        let new_size = align_up(new_size, PAGE_SIZE);
+        let end = addr + size;
+
+        if new_size <= size {
+            if new_size < size {
+                self.spans.lock().release(addr + new_size, size - new_size);
+                self.live_spans.set(addr, new_size);
             }
-            drop(pages);
+            return Some(true);
+        }
 
-            if let Some(new_page) = self.allocate_page() {
-                if let Some(addr) = (*new_page).alloc_small(size) {
-                    println!("chegou na nova pagina em alloc small");
-                    return addr as *mut u8;
-                }
+        // o lock de pages protege heap_current dentro de reserve_pages
+        let extra = new_size - size;
+        let _pages = self.pages.lock();
+        // reserve_pages pega o lock de spans para devolver sobras de alinhamento
+        let mut grown = self.spans.lock().take_free_at(end, extra);
+        if !grown && end == self.heap_current.load(Ordering::SeqCst) {
+            grown = self.reserve_pages(extra / PAGE_SIZE, PAGE_SIZE) == Some(end);
+        }
+        if grown {
+            self.live_spans.set(addr, new_size);
+        }
+        Some(grown)
+    }
+
+    // devolve o span que começa em `addr`, se houver um; a maioria dos frees é de blocos
+    // pequenos, que saem daqui sem pegar o lock de spans
+    fn dealloc_span(&self, addr: usize) -> bool {
+        match self.live_spans.remove(addr) {
+            Some(size) => {
+                self.spans.lock().release(addr, size);
+                true
             }
-        } else { 
-            println!("ta no large");
-            let pages = self.pages.lock();
-            println!("ta no large2, pages.len() => {}", pages.len());
-            for page in pages.iter() {
-                println!("ta no large3");
-                if let Some(addr) = page.alloc_large(size, align) {
-                    println!("chegou no alloc large");
-                    return addr as *mut u8;
-                }
+            None => false,
+        }
+    }
+
+    pub unsafe fn alloc(&self, size: usize, align: usize) -> *mut u8 {
+        let ptr = self.alloc_block(size, align);
+        if !ptr.is_null() {
+            self.count_alloc(size, is_large_object(size, align));
+        }
+        ptr
+    }
+
+    unsafe fn alloc_block(&self, size: usize, align: usize) -> *mut u8 {
+        // antes do heap ser mapeado qualquer acesso causaria page fault
+        if !self.is_ready() {
+            return null_mut();
+        }
+
+        if is_large_object(size, align) {
+            return self.alloc_span(size, align, false);
+        }
+
+        // os tamanhos que passam pelo cache são arredondados para a classe, assim
+        // qualquer bloco guardado numa classe comporta qualquer pedido dela
+        let class = size_class(size);
+        let size = block_size(size);
+
+        if let Some(class) = class {
+            if let Some(addr) = self.magazine_alloc(class, align) {
+                return addr as *mut u8;
             }
-            println!("ta no large4");
-            drop(pages);
-            println!("dropou");
-
-            if let Some(new_page) = self.allocate_page() {
-                println!("allocate_page");
-                if let Some(addr) = (*new_page).alloc_large(size, align) {
-                    println!("chegou na nova página em alloc large");
-                    return addr as *mut u8;
+        }
+
+        let mut pages = self.pages.lock();
+        self.alloc_from_pages(&mut pages, class, size, align)
+            .map_or(null_mut(), |addr| addr as *mut u8)
+    }
+
+    fn alloc_from_pages(
+        &self,
+        pages: &mut PageList,
+        class: Option<usize>,
+        size: usize,
+        align: usize,
+    ) -> Option<usize> {
+        match class {
+            // slots de slab ficam alinhados ao tamanho da classe
+            Some(class) if class < SLAB_CLASSES && align <= size => self.alloc_slab(pages, class),
+            _ => {
+                for page in pages.iter_mut().filter(|page| page.kind == PageKind::Large) {
+                    if let Some(addr) = page.alloc_large(size, align) {
+                        return Some(addr);
+                    }
                 }
+                self.allocate_page(pages, PageKind::Large)?.alloc_large(size, align)
             }
         }
-        println!("null_mut");
-        null_mut()
     }
 
     pub unsafe fn dealloc(&self, ptr: *mut u8, size: usize) {
         let addr = ptr as usize;
-        let pages = self.pages.lock();
-        let mut cache = self.cache.lock();
-
-        if size <= MAX_CACHEABLE_SIZE {
-            cache.push(addr);
+        if self.dealloc_span(addr) {
+            self.count_dealloc(size, true);
             return;
         }
+        self.count_dealloc(size, false);
+
+        // mesmo arredondamento feito em alloc, para achar a classe e o registro do bloco
+        let class = size_class(size);
+        let size = block_size(size);
+
+        if let Some(class) = class {
+            if self.magazine_free(class, addr) {
+                return;
+            }
+        }
 
+        let mut pages = self.pages.lock();
+        self.release_to_pages(&mut pages, addr, size);
+    }
+
+    fn release_to_pages(&self, pages: &mut PageList, addr: usize, size: usize) {
+        if let Some(index) = pages.iter().position(|page| addr >= page.start && addr < page.start + PAGE_SIZE) {
+            match pages[index].kind {
+                PageKind::Slab(class) => self.slabs.lock()[class].free(pages, index, addr),
+                PageKind::Large => pages[index].dealloc_large(addr, size),
+            }
+        }
+    }
+
+    fn alloc_slab(&self, pages: &mut PageList, class: usize) -> Option<usize> {
+        let mut slabs = self.slabs.lock();
+        let index = self.slab_page(pages, &mut slabs, class)?;
+
+        let addr = pages[index].alloc_slot()?;
+        if pages[index].slab_free == 0 {
+            slabs[class].partial.remove(pages, index);
+            slabs[class].full.push(pages, index);
+        }
+        Some(addr)
+    }
+
+    // página parcial da classe, senão uma vazia (desta ou de outra classe), senão
+    // uma página nova do heap
+    fn slab_page(
+        &self,
+        pages: &mut PageList,
+        slabs: &mut [SlabClass; SLAB_CLASSES],
+        class: usize,
+    ) -> Option<usize> {
+        if slabs[class].partial.head != NO_PAGE {
+            return Some(slabs[class].partial.head);
+        }
+
+        let donor = (0..SLAB_CLASSES)
+            .map(|i| (class + i) % SLAB_CLASSES)
+            .find(|&c| slabs[c].empty.head != NO_PAGE);
+        let index = match donor {
+            Some(donor) => {
+                let index = slabs[donor].empty.head;
+                slabs[donor].empty.remove(pages, index);
+                index
+            }
+            None => {
+                self.allocate_page(pages, PageKind::Slab(class))?;
+                pages.len() - 1
+            }
+        };
+
+        pages[index].init_slab(class);
+        slabs[class].partial.push(pages, index);
+        Some(index)
+    }
+
+    pub unsafe fn alloc_zeroed(&self, size: usize, align: usize) -> *mut u8 {
+        if self.is_ready() && is_large_object(size, align) {
+            let ptr = self.alloc_span(size, align, true);
+            if !ptr.is_null() {
+                self.count_alloc(size, true);
+            }
+            return ptr;
+        }
+
+        let ptr = self.alloc(size, align);
+        if !ptr.is_null() {
+            core::ptr::write_bytes(ptr, 0, size);
+        }
+        ptr
+    }
+
+    // tenta mudar o tamanho do bloco no lugar, senão aloca, copia e libera
+    pub unsafe fn realloc(&self, ptr: *mut u8, size: usize, align: usize, new_size: usize) -> *mut u8 {
+        if let Some(span) = self.realloc_in_place(ptr as usize, size, align, new_size) {
+            self.count_dealloc(size, span);
+            self.count_alloc(new_size, span);
+            return ptr;
+        }
+
+        let new_ptr = self.alloc(new_size, align);
+        if !new_ptr.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, new_ptr, size.min(new_size));
+            self.dealloc(ptr, size);
+        }
+        new_ptr
+    }
+
+    // Some(é um span) se o bloco mudou de tamanho sem sair do lugar
+    fn realloc_in_place(&self, addr: usize, size: usize, align: usize, new_size: usize) -> Option<bool> {
+        if let Some(resized) = self.resize_span(addr, new_size) {
+            return resized.then_some(true);
+        }
+        if is_large_object(new_size, align) {
+            return None;
+        }
+
+        let old_block = block_size(size);
+        let new_block = block_size(new_size);
+        if old_block == new_block {
+            return Some(false);
+        }
+
+        let mut pages = self.pages.lock();
+        let resized = match pages.iter_mut().find(|page| addr >= page.start && addr < page.start + PAGE_SIZE) {
+            // um slot de slab comporta qualquer tamanho até o da sua classe
+            Some(page) if page.kind != PageKind::Large => new_block <= page.slab_block_size(),
+            Some(page) => page.resize_large(addr, old_block, new_block),
+            None => false,
+        };
+        resized.then_some(false)
+    }
+
+    // bytes realmente ocupados por um pedido de `size` e a classe dele, se tiver
+    fn charge(size: usize, span: bool) -> (usize, Option<usize>) {
+        if span {
+            (align_up(size, PAGE_SIZE), None)
+        } else {
+            (block_size(size), size_class(size))
+        }
+    }
+
+    fn count_alloc(&self, size: usize, span: bool) {
+        let (bytes, class) = Self::charge(size, span);
+        let allocated = self.allocated.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        self.peak.fetch_max(allocated, Ordering::SeqCst);
+        if let Some(class) = class {
+            self.class_counts[class].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn count_dealloc(&self, size: usize, span: bool) {
+        let (bytes, class) = Self::charge(size, span);
+        self.allocated.fetch_sub(bytes, Ordering::SeqCst);
+        if let Some(class) = class {
+            self.class_counts[class].fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let heap_end = self.heap_end.load(Ordering::SeqCst);
+        let heap_size = heap_end - self._heap_start;
+        let bytes_allocated = self.allocated.load(Ordering::SeqCst);
+        let bytes_free = heap_size.saturating_sub(bytes_allocated);
+
+        // o maior pedaço contíguo que ainda pode ser entregue, começando pela parte
+        // mapeada que nenhuma página ou span usou ainda
+        let pages = self.pages.lock();
+        let spans = self.spans.lock();
+        let mut largest_free_block = heap_end.saturating_sub(self.heap_current.load(Ordering::SeqCst));
         for page in pages.iter() {
-            if addr >= page.start && addr < page.start + PAGE_SIZE {
-                if size <= SMALL_BLOCK_SIZE {
-                    page.dealloc_small(addr);
-                } else {
-                    page.dealloc_large(addr, size);
+            let largest_in_page = match page.kind {
+                PageKind::Large => page.free_blocks.iter()
+                    .map(|&(_, size)| size)
+                    .fold(PAGE_SIZE - page.current_offset, usize::max),
+                PageKind::Slab(_) if page.slab_free == page.slab_slots() => PAGE_SIZE,
+                PageKind::Slab(_) if page.slab_free > 0 => page.slab_block_size(),
+                PageKind::Slab(_) => 0,
+            };
+            largest_free_block = largest_free_block.max(largest_in_page);
+        }
+        for (_, size) in spans.free_spans() {
+            largest_free_block = largest_free_block.max(size);
+        }
+        let span_pages: usize = self.live_spans.iter().map(|(_, size)| size / PAGE_SIZE).sum();
+
+        let fragmentation_percent = if bytes_free == 0 {
+            0
+        } else {
+            100 - largest_free_block.min(bytes_free) * 100 / bytes_free
+        };
+
+        HeapStats {
+            heap_size,
+            max_size: self.max_size(),
+            bytes_allocated,
+            bytes_free,
+            peak_allocated: self.peak.load(Ordering::SeqCst),
+            pages: pages.len() + span_pages,
+            class_counts: core::array::from_fn(|i| self.class_counts[i].load(Ordering::SeqCst)),
+            largest_free_block,
+            fragmentation_percent,
+        }
+    }
+
+    // tira um bloco do magazine da cpu atual; se o bloco do topo não respeita o
+    // alinhamento pedido quem chamou segue pelo caminho normal das páginas
+    fn magazine_alloc(&self, class: usize, align: usize) -> Option<usize> {
+        let mut cache = self.cpus[cpu::id()].lock();
+        let cache = &mut *cache;
+        let (loaded, previous) = (&mut cache.loaded[class], &mut cache.previous[class]);
+
+        if loaded.is_empty() {
+            if !previous.is_empty() {
+                core::mem::swap(loaded, previous);
+            } else if let Some(full) = self.depot.lock()[class].pop() {
+                *loaded = full;
+            } else {
+                self.refill(class, loaded);
+            }
+        }
+
+        match loaded.last() {
+            Some(&addr) if addr % align == 0 => loaded.pop(),
+            _ => None,
+        }
+    }
+
+    // guarda o bloco no magazine da cpu atual, false se ele tem que voltar para as páginas
+    fn magazine_free(&self, class: usize, addr: usize) -> bool {
+        let mut cache = self.cpus[cpu::id()].lock();
+        let cache = &mut *cache;
+        let (loaded, previous) = (&mut cache.loaded[class], &mut cache.previous[class]);
+
+        if loaded.len() == MAGAZINE_SIZE {
+            if previous.len() == MAGAZINE_SIZE {
+                // o previous cheio vai para o depósito, ou de volta para as páginas
+                // se o depósito também estiver cheio
+                if !self.depot.lock()[class].push(*previous) {
+                    self.flush(class, previous);
                 }
-                cache.push(addr);
-                return;
+                previous.clear();
             }
+            core::mem::swap(loaded, previous);
         }
+        loaded.push(addr)
     }
+
+    // enche metade do magazine de uma vez, pegando o lock das páginas uma vez só
+    fn refill(&self, class: usize, magazine: &mut Magazine) {
+        let size = SIZE_CLASSES[class];
+        let mut pages = self.pages.lock();
+        while magazine.len() < MAGAZINE_SIZE / 2 {
+            match self.alloc_from_pages(&mut pages, Some(class), size, size.min(PAGE_SIZE)) {
+                Some(addr) => magazine.push(addr),
+                None => break,
+            };
+        }
+    }
+
+    // devolve para as páginas todos os blocos parados nos magazines e no depósito,
+    // true se havia algum
+    fn reclaim_caches(&self) -> bool {
+        let mut reclaimed = false;
+        for cpu in self.cpus.iter() {
+            let mut cache = cpu.lock();
+            let cache = &mut *cache;
+            for class in 0..SIZE_CLASSES.len() {
+                for magazine in [&mut cache.loaded[class], &mut cache.previous[class]] {
+                    reclaimed |= !magazine.is_empty();
+                    self.flush(class, magazine);
+                    magazine.clear();
+                }
+            }
+        }
+        let mut depot = self.depot.lock();
+        for (class, magazines) in depot.iter_mut().enumerate() {
+            for magazine in magazines.iter() {
+                reclaimed = true;
+                self.flush(class, magazine);
+            }
+            magazines.clear();
+        }
+        reclaimed
+    }
+
+    // um pedido que falhou: primeiro os caches voltam para as páginas, depois os
+    // callbacks de falta de memória (oom) têm a chance de liberar alguma coisa
+    fn recover(&self, layout: Layout, mut retry: impl FnMut() -> *mut u8) -> *mut u8 {
+        if !self.is_ready() {
+            return null_mut();
+        }
+        if without_interrupts(|| self.reclaim_caches()) {
+            let ptr = retry();
+            if !ptr.is_null() {
+                return ptr;
+            }
+        }
+        oom::handle(layout, retry)
+    }
+
+    fn flush(&self, class: usize, magazine: &Magazine) {
+        let mut pages = self.pages.lock();
+        for &addr in magazine.iter() {
+            self.release_to_pages(&mut pages, addr, SIZE_CLASSES[class]);
+        }
+    }
+}
+
+// registra o resultado de uma operação no rastreamento (não faz nada sem a feature alloc-trace)
+fn trace(kind: EventKind, ptr: *mut u8, size: usize, align: usize, old_addr: usize) {
+    let kind = if ptr.is_null() { EventKind::Fail } else { kind };
+    alloc_trace::record(Event { kind, addr: ptr as usize, size, align, old_addr });
 }
 
+// as interrupções ficam desligadas enquanto os locks internos estão pegos, senão um
+// handler que aloca na mesma cpu ficaria girando num lock que nunca vai ser solto
 unsafe impl GlobalAlloc for CombinedAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.alloc(layout.size(), layout.align())
+        let alloc = || without_interrupts(|| self.alloc(layout.size(), layout.align()));
+        let mut ptr = alloc();
+        if ptr.is_null() {
+            ptr = self.recover(layout, alloc);
+        }
+        trace(EventKind::Alloc, ptr, layout.size(), layout.align(), 0);
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.dealloc(ptr, layout.size())
+        trace(EventKind::Dealloc, ptr, layout.size(), layout.align(), 0);
+        without_interrupts(|| self.dealloc(ptr, layout.size()))
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let alloc_zeroed = || without_interrupts(|| self.alloc_zeroed(layout.size(), layout.align()));
+        let mut ptr = alloc_zeroed();
+        if ptr.is_null() {
+            ptr = self.recover(layout, alloc_zeroed);
+        }
+        trace(EventKind::Alloc, ptr, layout.size(), layout.align(), 0);
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let realloc = || without_interrupts(|| self.realloc(ptr, layout.size(), layout.align(), new_size));
+        let mut new_ptr = realloc();
+        if new_ptr.is_null() {
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            new_ptr = self.recover(new_layout, realloc);
+        }
+        trace(EventKind::Realloc, new_ptr, new_size, layout.align(), ptr as usize);
+        new_ptr
     }
 }
 
-#[global_allocator]
+// com alloc-debug ou alloc-leak quem vai para o #[global_allocator] é a camada de cima,
+// que usa este por baixo
+#[cfg_attr(all(feature = "allocator-combined", not(any(feature = "alloc-debug", feature = "alloc-leak"))), global_allocator)]
 pub static ALLOCATOR: CombinedAllocator = CombinedAllocator::new(HEAP_START, HEAP_SIZE);
\ No newline at end of file
@@ -0,0 +1,51 @@
+//identificação do processador atual, usada pelas estruturas por cpu
+use core::arch::x86_64::__cpuid;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use x86_64::registers::model_specific::GsBase;
+use x86_64::VirtAddr;
+
+// quantos processadores as estruturas por cpu comportam
+pub const MAX_CPUS: usize = 8;
+
+// o que cada cpu acha em gs:0; o id é o primeiro campo
+#[repr(C)]
+struct PerCpu {
+    id: usize,
+}
+
+static PER_CPU: [PerCpu; MAX_CPUS] = {
+    let mut cpus = [const { PerCpu { id: 0 } }; MAX_CPUS];
+    let mut i = 0;
+    while i < MAX_CPUS {
+        cpus[i].id = i;
+        i += 1;
+    }
+    cpus
+};
+
+// antes de init o GS base é 0 e ler gs:0 seria um page fault
+static READY: AtomicBool = AtomicBool::new(false);
+
+//aponta o GS base desta cpu para a entrada dela em PER_CPU, feito uma vez por cpu antes dela
+//usar as estruturas por cpu. o id vem do APIC local (cpuid folha 1); cpuid serializa a cpu
+//(e numa vm sai para o hipervisor), por isso ele só roda aqui e não a cada id()
+#[allow(unused_unsafe)] // __cpuid deixou de ser unsafe nas versões mais novas do core
+pub fn init() {
+    let apic_id = unsafe { __cpuid(1) }.ebx >> 24;
+    let cpu = &PER_CPU[apic_id as usize % MAX_CPUS];
+    GsBase::write(VirtAddr::from_ptr(cpu));
+    READY.store(true, Ordering::Release);
+}
+
+// índice do processador que está executando, entre 0 e MAX_CPUS - 1; antes de init só a
+// cpu de boot está rodando, e ela fica com o 0
+#[inline]
+pub fn id() -> usize {
+    if !READY.load(Ordering::Acquire) {
+        return 0;
+    }
+    let id: usize;
+    unsafe { core::arch::asm!("mov {}, gs:[0]", out(reg) id, options(nostack, readonly, preserves_flags)) };
+    id
+}
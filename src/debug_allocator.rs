@@ -0,0 +1,181 @@
+//modo de depuração do heap (feature `alloc-debug`)
+//cada bloco ganha um cabeçalho e zonas vermelhas antes e depois dos dados; a memória
+//liberada é envenenada e fica um tempo em quarentena antes de voltar para o alocador
+//de baixo, assim escritas fora dos limites, double frees e uso depois do free viram
+//um panic com o endereço e o layout do bloco em vez de corromper o heap em silêncio
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ptr::null_mut;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::combined_allocator::{CombinedAllocator, HEAP_MAX_SIZE, HEAP_START};
+
+const RED_ZONE: usize = 16;
+const RED_ZONE_BYTE: u8 = 0xFD;
+// dados recém alocados, para leituras de memória não inicializada chamarem atenção
+const ALLOC_BYTE: u8 = 0xAA;
+const FREED_BYTE: u8 = 0xDD;
+
+const MAGIC_LIVE: usize = 0xA110_CA7E_D0B1_0C00;
+const MAGIC_FREED: usize = 0xF4EE_D0B1_0C00_DEAD;
+
+// blocos liberados que ainda não voltaram para o alocador de baixo
+const QUARANTINE: usize = 64;
+
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+    align: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    DoubleFree,
+    // o ponteiro não saiu deste alocador
+    ForeignPointer,
+    // o layout do free não é o mesmo do alloc
+    LayoutMismatch,
+    FrontRedZone,
+    BackRedZone,
+    // o bloco foi escrito enquanto estava na quarentena
+    UseAfterFree,
+}
+
+// espaço antes dos dados: cabeçalho + zona vermelha, arredondado para o alinhamento pedido
+fn front_size(align: usize) -> usize {
+    let front = size_of::<Header>() + RED_ZONE;
+    (front + align - 1) & !(align - 1)
+}
+
+fn inner_layout(layout: Layout) -> Layout {
+    let align = layout.align().max(size_of::<usize>());
+    let size = front_size(align) + layout.size() + RED_ZONE;
+    unsafe { Layout::from_size_align_unchecked(size, align) }
+}
+
+fn report(kind: Corruption, addr: usize, layout: Layout) -> ! {
+    panic!(
+        "heap corruption: {:?} at {:#x} (size {}, align {})",
+        kind, addr, layout.size(), layout.align()
+    );
+}
+
+unsafe fn all_bytes(start: usize, len: usize, value: u8) -> bool {
+    (start..start + len).all(|addr| *(addr as *const u8) == value)
+}
+
+struct Quarantine {
+    entries: [(usize, Layout); QUARANTINE],
+    head: usize,
+    len: usize,
+}
+
+impl Quarantine {
+    // devolve o bloco mais antigo quando a fila está cheia
+    fn push(&mut self, addr: usize, layout: Layout) -> Option<(usize, Layout)> {
+        let slot = (self.head + self.len) % QUARANTINE;
+        if self.len < QUARANTINE {
+            self.entries[slot] = (addr, layout);
+            self.len += 1;
+            return None;
+        }
+        let evicted = self.entries[self.head];
+        self.entries[self.head] = (addr, layout);
+        self.head = (self.head + 1) % QUARANTINE;
+        Some(evicted)
+    }
+}
+
+pub struct DebugAllocator<A: 'static> {
+    inner: &'static A,
+    quarantine: Mutex<Quarantine>,
+}
+
+impl<A: GlobalAlloc> DebugAllocator<A> {
+    pub const fn new(inner: &'static A) -> Self {
+        DebugAllocator {
+            inner,
+            quarantine: Mutex::new(Quarantine {
+                entries: [(0, Layout::new::<u8>()); QUARANTINE],
+                head: 0,
+                len: 0,
+            }),
+        }
+    }
+
+    // confere o cabeçalho e as zonas vermelhas de um bloco vivo, sem mudar nada
+    pub unsafe fn check(&self, ptr: *mut u8, layout: Layout) -> Result<(), Corruption> {
+        let addr = ptr as usize;
+        let front = front_size(inner_layout(layout).align());
+        // fora da janela do heap nem dá para ler o cabeçalho com segurança
+        if addr < HEAP_START + front || addr >= HEAP_START + HEAP_MAX_SIZE {
+            return Err(Corruption::ForeignPointer);
+        }
+
+        let header = &*((addr - front) as *const Header);
+        match header.magic {
+            MAGIC_LIVE => {}
+            MAGIC_FREED => return Err(Corruption::DoubleFree),
+            _ => return Err(Corruption::ForeignPointer),
+        }
+        if header.size != layout.size() || header.align != layout.align() {
+            return Err(Corruption::LayoutMismatch);
+        }
+
+        let red_start = addr - front + size_of::<Header>();
+        if !all_bytes(red_start, addr - red_start, RED_ZONE_BYTE) {
+            return Err(Corruption::FrontRedZone);
+        }
+        if !all_bytes(addr + layout.size(), RED_ZONE, RED_ZONE_BYTE) {
+            return Err(Corruption::BackRedZone);
+        }
+        Ok(())
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let inner_layout = inner_layout(layout);
+        let base = self.inner.alloc(inner_layout);
+        if base.is_null() {
+            return null_mut();
+        }
+
+        let front = front_size(inner_layout.align());
+        let base = base as usize;
+        let addr = base + front;
+        *(base as *mut Header) = Header { magic: MAGIC_LIVE, size: layout.size(), align: layout.align() };
+        let red_start = (base + size_of::<Header>()) as *mut u8;
+        red_start.write_bytes(RED_ZONE_BYTE, addr - red_start as usize);
+        (addr as *mut u8).write_bytes(ALLOC_BYTE, layout.size());
+        ((addr + layout.size()) as *mut u8).write_bytes(RED_ZONE_BYTE, RED_ZONE);
+        addr as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Err(kind) = self.check(ptr, layout) {
+            report(kind, ptr as usize, layout);
+        }
+
+        let addr = ptr as usize;
+        let front = front_size(inner_layout(layout).align());
+        (*((addr - front) as *mut Header)).magic = MAGIC_FREED;
+        ptr.write_bytes(FREED_BYTE, layout.size());
+
+        let evicted = without_interrupts(|| self.quarantine.lock().push(addr, layout));
+        if let Some((old, old_layout)) = evicted {
+            // o veneno precisa estar intacto, senão alguém usou o bloco depois do free
+            if !all_bytes(old, old_layout.size(), FREED_BYTE) {
+                report(Corruption::UseAfterFree, old, old_layout);
+            }
+            let old_front = front_size(inner_layout(old_layout).align());
+            self.inner.dealloc((old - old_front) as *mut u8, inner_layout(old_layout));
+        }
+    }
+}
+
+#[cfg_attr(all(feature = "alloc-debug", not(feature = "alloc-leak")), global_allocator)]
+pub static ALLOCATOR: DebugAllocator<CombinedAllocator> = DebugAllocator::new(&crate::combined_allocator::ALLOCATOR);
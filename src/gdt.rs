@@ -1,25 +1,23 @@
 use x86_64::VirtAddr;
 use x86_64::structures::tss::TaskStateSegment;
 use lazy_static::lazy_static;
-use core::ptr::addr_of;
+use core::ptr::{addr_of, addr_of_mut};
 
 use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor, SegmentSelector};
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+// o page fault tem pilha própria para que um estouro de pilha chegue até o handler
+pub const PAGE_FAULT_IST_INDEX: u16 = 1;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { addr_of!(STACK) });
-            let stack_end = stack_start + STACK_SIZE.try_into().unwrap();
-            stack_end
-        };
-        tss
-    };
+//pilhas estáticas usadas só até o vmm existir, depois stack::init troca por pilhas com guarda
+const BOOT_IST_STACK_SIZE: usize = 4096 * 5;
+static mut DOUBLE_FAULT_STACK: [u8; BOOT_IST_STACK_SIZE] = [0; BOOT_IST_STACK_SIZE];
+static mut PAGE_FAULT_STACK: [u8; BOOT_IST_STACK_SIZE] = [0; BOOT_IST_STACK_SIZE];
+
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+fn stack_end(stack: *const [u8; BOOT_IST_STACK_SIZE]) -> VirtAddr {
+    VirtAddr::from_ptr(stack) + BOOT_IST_STACK_SIZE as u64
 }
 
 struct Selectors {
@@ -31,15 +29,25 @@ lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.append(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.append(Descriptor::tss_segment(&TSS));
+        let tss_selector = gdt.append(Descriptor::tss_segment(unsafe { &*addr_of!(TSS) }));
         (gdt, Selectors { code_selector, tss_selector })
     };
 }
 
+//troca a pilha de uma entrada da IST; a cpu lê a TSS a cada interrupção, não precisa recarregar
+pub fn set_ist_stack(index: u16, top: VirtAddr) {
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        (*addr_of_mut!(TSS)).interrupt_stack_table[index as usize] = top;
+    });
+}
+
 pub fn init() {
     use x86_64::instructions::tables::load_tss;
     use x86_64::instructions::segmentation::{CS, Segment};
-    
+
+    set_ist_stack(DOUBLE_FAULT_IST_INDEX, stack_end(addr_of!(DOUBLE_FAULT_STACK)));
+    set_ist_stack(PAGE_FAULT_IST_INDEX, stack_end(addr_of!(PAGE_FAULT_STACK)));
+
     GDT.0.load();
     unsafe {
         CS::set_reg(GDT.1.code_selector);
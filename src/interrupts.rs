@@ -9,6 +9,7 @@ use crate::vga_buffer::print_char;
 
 use x86_64::structures::idt::PageFaultErrorCode;
 use crate::hlt_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use pic8259::ChainedPics;
 
@@ -43,12 +44,34 @@ extern "x86-interrupt" fn double_fault_handler(
     panic!("\nEXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
+// o page fault roda na IST1 e a cpu sempre começa uma entrada da IST no topo da pilha, então
+// um page fault dentro do handler (inclusive um estouro da própria IST1, que cai na guarda
+// dela) escreve o frame novo por cima do que está em uso; o de fora não tem mais como voltar
+static IN_PAGE_FAULT: AtomicBool = AtomicBool::new(false);
+
 extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
     use x86_64::registers::control::Cr2;
 
+    // por isso o aninhado é tratado como um double fault: fatal, sem tentar voltar
+    if IN_PAGE_FAULT.swap(true, Ordering::Relaxed) {
+        panic!("\nEXCEPTION: PAGE FAULT INSIDE THE PAGE FAULT HANDLER\naddress: {:?}\n{:#?}", Cr2::read(), stack_frame);
+    }
+
+    if let Ok(addr) = Cr2::read() {
+        // acesso à página de guarda de uma pilha do kernel
+        if let Some(name) = crate::stack::guard_hit(addr) {
+            panic!("stack overflow in {}", name);
+        }
+        // página ainda sem frame de um intervalo com paginação sob demanda, a instrução é repetida
+        if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) && crate::vmm::handle_demand_fault(addr) {
+            IN_PAGE_FAULT.store(false, Ordering::Relaxed);
+            return;
+        }
+    }
+
     println!("EXCEPTION: PAGE FAULT");
     println!("Accessed Address: {:?}", Cr2::read());
     println!("Error Code: {:?}", error_code);
@@ -56,9 +79,23 @@ extern "x86-interrupt" fn page_fault_handler(
     hlt_loop();
 }
 
+// função chamada a cada tick do timer, usada pelos testes para rodar código em contexto de interrupção
+static TIMER_CALLBACK: spin::Mutex<Option<fn()>> = spin::Mutex::new(None);
+
+pub fn set_timer_callback(callback: Option<fn()>) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *TIMER_CALLBACK.lock() = callback;
+    });
+}
+
 extern "x86-interrupt" fn timer_interrupt_handler(
     _stack_frame: InterruptStackFrame)
 {
+    let callback = *TIMER_CALLBACK.lock();
+    if let Some(callback) = callback {
+        callback();
+    }
+
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
@@ -116,8 +153,9 @@ lazy_static! {
         unsafe {
             idt.double_fault.set_handler_fn(double_fault_handler)
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.page_fault.set_handler_fn(page_fault_handler)
+                .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
         }
-        idt.page_fault.set_handler_fn(page_fault_handler);
         idt[InterruptIndex::Timer.as_u8()]
             .set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_u8()]
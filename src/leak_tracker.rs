@@ -0,0 +1,274 @@
+//rastreador de vazamentos opcional (feature `alloc-leak`)
+//fica por fora de todos os outros alocadores e guarda cada alocação viva numa tabela
+//de tamanho fixo; um checkpoint é só o número da próxima alocação, então dá para
+//listar ou exigir que nada alocado depois dele continue vivo
+//
+//o endereço de retorno do próprio alloc cai na cola do liballoc (__rust_alloc), então cada
+//alocação guarda alguns endereços de retorno seguindo a cadeia de rbp; só faz sentido com
+//`-C force-frame-pointers=yes` no RUSTFLAGS, sem isso a cadeia para cedo ou vem com lixo
+
+#[cfg(feature = "alloc-leak")]
+use core::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "alloc-leak")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "alloc-leak")]
+use spin::Mutex;
+#[cfg(feature = "alloc-leak")]
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::serial_println;
+
+#[cfg(all(feature = "alloc-leak", feature = "allocator-combined", not(feature = "alloc-debug")))]
+use crate::combined_allocator::{CombinedAllocator as Tracked, ALLOCATOR as TRACKED};
+#[cfg(all(feature = "alloc-leak", feature = "alloc-debug"))]
+use crate::{combined_allocator::CombinedAllocator, debug_allocator::{DebugAllocator, ALLOCATOR as TRACKED}};
+#[cfg(all(feature = "alloc-leak", feature = "alloc-debug"))]
+type Tracked = DebugAllocator<CombinedAllocator>;
+#[cfg(all(feature = "alloc-leak", feature = "allocator-buddy"))]
+use crate::buddy_allocator::{BuddyAllocator as Tracked, ALLOCATOR as TRACKED};
+
+// quantos endereços de retorno cada alocação guarda
+pub const CALLER_DEPTH: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub addr: usize,
+    pub size: usize,
+    pub align: usize,
+    // endereços de retorno a partir do alocador, 0 onde a cadeia acabou; os primeiros são
+    // do liballoc, o primeiro fora dele é quem alocou (addr2line acha a função)
+    pub callers: [usize; CALLER_DEPTH],
+    // número da alocação, comparado com o checkpoint
+    pub seq: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    seq: usize,
+    // alocações fora da tabela até aqui, para saber se alguma depois ficou sem rastreio
+    dropped: usize,
+}
+
+#[cfg(feature = "alloc-leak")]
+const TABLE_SIZE: usize = 1024;
+
+#[cfg(feature = "alloc-leak")]
+struct Table {
+    // addr 0 marca posição vazia
+    entries: [Allocation; TABLE_SIZE],
+    // alocações que não couberam na tabela e por isso não são rastreadas
+    dropped: usize,
+}
+
+#[cfg(feature = "alloc-leak")]
+impl Table {
+    fn insert(&mut self, allocation: Allocation) {
+        match self.entries.iter_mut().find(|entry| entry.addr == 0) {
+            Some(entry) => *entry = allocation,
+            None => self.dropped += 1,
+        }
+    }
+
+    fn find(&mut self, addr: usize) -> Option<&mut Allocation> {
+        self.entries.iter_mut().find(|entry| entry.addr == addr)
+    }
+}
+
+#[cfg(feature = "alloc-leak")]
+pub struct LeakTracker<A: 'static> {
+    inner: &'static A,
+    next_seq: AtomicUsize,
+    table: Mutex<Table>,
+}
+
+#[cfg(feature = "alloc-leak")]
+impl<A: GlobalAlloc> LeakTracker<A> {
+    pub const fn new(inner: &'static A) -> Self {
+        const EMPTY: Allocation = Allocation { addr: 0, size: 0, align: 0, callers: [0; CALLER_DEPTH], seq: 0 };
+        LeakTracker {
+            inner,
+            next_seq: AtomicUsize::new(0),
+            table: Mutex::new(Table { entries: [EMPTY; TABLE_SIZE], dropped: 0 }),
+        }
+    }
+
+    fn record(&self, ptr: *mut u8, layout: Layout, callers: [usize; CALLER_DEPTH]) {
+        if ptr.is_null() {
+            return;
+        }
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let allocation = Allocation {
+            addr: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            callers,
+            seq,
+        };
+        without_interrupts(|| self.table.lock().insert(allocation));
+    }
+
+    fn forget(&self, ptr: *mut u8) {
+        without_interrupts(|| {
+            if let Some(entry) = self.table.lock().find(ptr as usize) {
+                entry.addr = 0;
+            }
+        });
+    }
+}
+
+// segue a cadeia de rbp a partir de quem chamou; cada frame é (rbp anterior, retorno).
+// só lê dentro do intervalo do vmm da pilha atual, acima do rsp, então um rbp que não é
+// ponteiro de frame só encerra a cadeia
+#[cfg(feature = "alloc-leak")]
+#[inline(always)]
+fn callers() -> [usize; CALLER_DEPTH] {
+    let (rsp, rbp): (usize, usize);
+    unsafe {
+        core::arch::asm!("mov {}, rsp", "mov {}, rbp", out(reg) rsp, out(reg) rbp, options(nomem, nostack));
+    }
+    let mut callers = [0; CALLER_DEPTH];
+    // try_find: o alocador pode ser chamado com qualquer lock pego
+    let stack_end = match crate::vmm::try_find(x86_64::VirtAddr::new(rsp as u64)) {
+        Some(region) => region.end() as usize,
+        None => return callers,
+    };
+
+    let mut frame = rbp;
+    for caller in callers.iter_mut() {
+        if frame < rsp || frame % 8 != 0 || frame + 16 > stack_end {
+            break;
+        }
+        let (next, ret) = unsafe { (*(frame as *const usize), *((frame + 8) as *const usize)) };
+        *caller = ret;
+        // a pilha cresce para baixo, os frames de quem chamou ficam sempre acima
+        if next <= frame {
+            break;
+        }
+        frame = next;
+    }
+    callers
+}
+
+#[cfg(feature = "alloc-leak")]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for LeakTracker<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        self.record(ptr, layout, callers());
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.forget(ptr);
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        self.record(ptr, layout, callers());
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if new_ptr.is_null() {
+            return new_ptr;
+        }
+        // continua sendo a mesma alocação, só muda de lugar e de tamanho
+        without_interrupts(|| {
+            if let Some(entry) = self.table.lock().find(ptr as usize) {
+                entry.addr = new_ptr as usize;
+                entry.size = new_size;
+            }
+        });
+        new_ptr
+    }
+}
+
+#[cfg(feature = "alloc-leak")]
+#[global_allocator]
+pub static ALLOCATOR: LeakTracker<Tracked> = LeakTracker::new(&TRACKED);
+
+pub fn checkpoint() -> Checkpoint {
+    #[cfg(feature = "alloc-leak")]
+    return Checkpoint { seq: ALLOCATOR.next_seq.load(Ordering::SeqCst), dropped: dropped() };
+
+    #[cfg(not(feature = "alloc-leak"))]
+    Checkpoint { seq: 0, dropped: 0 }
+}
+
+// chama `f` com cada alocação feita depois do checkpoint que ainda está viva
+pub fn for_each_since(checkpoint: Checkpoint, mut f: impl FnMut(Allocation)) {
+    #[cfg(feature = "alloc-leak")]
+    without_interrupts(|| {
+        let table = ALLOCATOR.table.lock();
+        for entry in table.entries.iter() {
+            if entry.addr != 0 && entry.seq >= checkpoint.seq {
+                f(*entry);
+            }
+        }
+    });
+
+    #[cfg(not(feature = "alloc-leak"))]
+    let _ = (checkpoint, &mut f);
+}
+
+// alocações que não couberam na tabela, um relatório vazio só vale se isto for 0
+pub fn dropped() -> usize {
+    #[cfg(feature = "alloc-leak")]
+    return without_interrupts(|| ALLOCATOR.table.lock().dropped);
+
+    #[cfg(not(feature = "alloc-leak"))]
+    0
+}
+
+// escreve na serial as alocações ainda vivas desde o checkpoint
+pub fn report_since(checkpoint: Checkpoint) {
+    if !cfg!(feature = "alloc-leak") {
+        serial_println!("leak tracker disabled (build with --features alloc-leak)");
+        return;
+    }
+
+    serial_println!("live allocations since #{}:", checkpoint.seq);
+    let mut count = 0;
+    for_each_since(checkpoint, |a| {
+        count += 1;
+        crate::serial_print!("{:>6} addr={:#x} size={} align={} callers:", a.seq, a.addr, a.size, a.align);
+        for caller in a.callers.iter().take_while(|&&caller| caller != 0) {
+            crate::serial_print!(" {:#x}", caller);
+        }
+        serial_println!();
+    });
+    serial_println!("{} live, {} untracked", count, dropped());
+}
+
+// todas as alocações vivas
+pub fn report() {
+    report_since(Checkpoint { seq: 0, dropped: 0 });
+}
+
+// para os testes: nada alocado depois do checkpoint pode continuar vivo; uma alocação
+// que não coube na tabela pode ter vazado sem aparecer, então também falha. só existe com
+// alloc-leak, sem a tabela não haveria o que conferir e o teste passaria sem testar nada
+#[cfg(feature = "alloc-leak")]
+pub fn assert_no_new_allocations(checkpoint: Checkpoint) {
+    let mut leaked = 0;
+    for_each_since(checkpoint, |_| leaked += 1);
+    let untracked = dropped() - checkpoint.dropped;
+    if leaked > 0 || untracked > 0 {
+        report_since(checkpoint);
+    }
+    if leaked > 0 {
+        panic!("{} allocations leaked since checkpoint #{}", leaked, checkpoint.seq);
+    }
+    if untracked > 0 {
+        panic!("{} allocations since checkpoint #{} were not tracked (table full)", untracked, checkpoint.seq);
+    }
+}
+
+// roda `f` e confere que ele devolveu tudo o que alocou
+#[cfg(feature = "alloc-leak")]
+pub fn assert_no_leaks(f: impl FnOnce()) {
+    let start = checkpoint();
+    f();
+    assert_no_new_allocations(start);
+}
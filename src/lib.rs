@@ -14,8 +14,28 @@ pub mod serial;
 pub mod vga_buffer;
 pub mod interrupts;
 pub mod gdt;
+pub mod cpu;
 pub mod memory;
 pub mod combined_allocator;
+pub mod buddy_allocator;
+pub mod alloc_trace;
+pub mod debug_allocator;
+pub mod leak_tracker;
+pub mod meminfo;
+pub mod pagemap;
+pub mod vmm;
+pub mod stack;
+pub mod address_space;
+pub mod oom;
+
+#[cfg(all(feature = "allocator-combined", feature = "allocator-buddy"))]
+compile_error!("features `allocator-combined` and `allocator-buddy` are mutually exclusive");
+
+#[cfg(not(any(feature = "allocator-combined", feature = "allocator-buddy")))]
+compile_error!("one of the features `allocator-combined` or `allocator-buddy` must be enabled");
+
+#[cfg(all(feature = "alloc-debug", not(feature = "allocator-combined")))]
+compile_error!("feature `alloc-debug` only wraps `allocator-combined`");
 
 use core::panic::PanicInfo;
 
@@ -26,6 +46,7 @@ pub fn hlt_loop() -> ! {
 }
 
 pub fn init() {
+    cpu::init();
     gdt::init();
     interrupts::init_idt();
     unsafe { interrupts::PICS.lock().initialize() };
@@ -28,10 +28,11 @@ use gale_sys::memory;
 
 entry_point!(kernel_main);
 
-use gale_sys::combined_allocator::ALLOCATOR;
-
 extern crate alloc;
 
+//passa pelo #[global_allocator], seja qual for o escolhido pelas features
+use alloc::alloc::{alloc, dealloc, Layout};
+
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     println!("Inicializando");
     vga_buffer::print_in(30, 11, "Aguarde um Momento");
@@ -45,34 +46,46 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
 
     //mapeador da memória
-    unsafe { memory::init(phys_mem_offset) };
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
 
-    unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    //o heap precisa estar mapeado antes de qualquer alocação
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
 
     println!("antes de allocar");
 
-    let allocator = &ALLOCATOR;
+    let layout1 = Layout::from_size_align(4096, 8).unwrap();
 
     println!("antes de allocar2");
 
-    let ptr1 = unsafe { allocator.alloc(4096, 8) };
+    let ptr1 = unsafe { alloc(layout1) };
     if !ptr1.is_null() {
         println!("Alocação bem-sucedida: {:p}", ptr1);
-        unsafe { allocator.dealloc(ptr1, 4096) };
+        unsafe { dealloc(ptr1, layout1) };
         println!("Desalocação bem-sucedida");
     } else {
         println!("Falha na alocação");
     }
 
-    let ptr2 = unsafe { allocator.alloc(1890, 8) };
+    let layout2 = Layout::from_size_align(1890, 8).unwrap();
+    let ptr2 = unsafe { alloc(layout2) };
     if !ptr2.is_null() {
         println!("Alocação bem-sucedida: {:p}", ptr2);
-        unsafe { allocator.dealloc(ptr2, 1890) };
+        unsafe { dealloc(ptr2, layout2) };
         println!("Desalocação bem-sucedida");
     } else {
         println!("Falha na alocação");
     }
 
+    //com a feature alloc-trace as alocações acima aparecem na serial
+    #[cfg(feature = "alloc-trace")]
+    gale_sys::alloc_trace::dump();
+
+    gale_sys::meminfo::report();
+    gale_sys::pagemap::report_serial();
+
     #[cfg(test)]
     use x86_64::registers::control::Cr3;
 
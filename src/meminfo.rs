@@ -0,0 +1,65 @@
+//relatório de uso de memória: heap do alocador ativo e frames físicos
+
+use core::fmt;
+use x86_64::instructions::interrupts;
+
+use crate::combined_allocator::{HeapStats, SIZE_CLASSES};
+use crate::memory;
+use crate::serial::SERIAL1;
+use crate::vga_buffer::WRITER;
+
+// estatísticas do alocador escolhido como #[global_allocator]; os locks do alocador
+// são os mesmos que um handler de interrupção pegaria ao alocar
+pub fn heap_stats() -> HeapStats {
+    #[cfg(feature = "allocator-combined")]
+    return interrupts::without_interrupts(|| crate::combined_allocator::ALLOCATOR.stats());
+
+    #[cfg(feature = "allocator-buddy")]
+    return interrupts::without_interrupts(|| crate::buddy_allocator::ALLOCATOR.stats());
+}
+
+fn write_report(out: &mut impl fmt::Write) -> fmt::Result {
+    // as estatísticas são lidas antes de pegar os locks da saída
+    let heap = heap_stats();
+    let frames = memory::frame_stats();
+    let huge = crate::vmm::huge_page_stats();
+
+    writeln!(out, "meminfo:")?;
+    writeln!(out, "  heap:  {} KiB mapped of {} KiB, {} pages", heap.heap_size / 1024, heap.max_size / 1024, heap.pages)?;
+    writeln!(out, "  used:  {} bytes (peak {})", heap.bytes_allocated, heap.peak_allocated)?;
+    writeln!(
+        out,
+        "  free:  {} bytes, largest block {}, fragmentation {}%",
+        heap.bytes_free, heap.largest_free_block, heap.fragmentation_percent
+    )?;
+    write!(out, "  class:")?;
+    for (size, count) in SIZE_CLASSES.iter().zip(heap.class_counts.iter()) {
+        write!(out, " {}:{}", size, count)?;
+    }
+    writeln!(out)?;
+    match frames {
+        Some(frames) => writeln!(
+            out,
+            "  frames: {} used, {} free ({} reserved for the heap), {} total",
+            frames.used_frames, frames.free_frames, frames.reserved_frames, frames.total_frames
+        )?,
+        None => writeln!(out, "  frames: not initialized")?,
+    }
+    writeln!(out, "  huge pages: {} mapped, {} TLB entries saved", huge.huge_pages, huge.tlb_entries_saved)?;
+    writeln!(out, "  demand faults: {}", crate::vmm::demand_faults())
+}
+
+// só na serial, usado pelo caminho de falta de memória
+pub fn report_serial() {
+    interrupts::without_interrupts(|| {
+        write_report(&mut *SERIAL1.lock()).expect("Printing to serial failed");
+    });
+}
+
+// escreve o relatório na serial e no vga
+pub fn report() {
+    interrupts::without_interrupts(|| {
+        write_report(&mut *SERIAL1.lock()).expect("Printing to serial failed");
+        write_report(&mut *WRITER.lock()).unwrap();
+    });
+}
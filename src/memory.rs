@@ -18,7 +18,7 @@ pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static>
 }
 
 //ativa a pagina de level4
-unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
+pub(crate) unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
     -> &'static mut PageTable
 {
     use x86_64::registers::control::Cr3;
@@ -34,28 +34,80 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
     &mut *page_table_ptr
 }
 
-//alocador de frame que retorna o memory map
+//alocador de frame baseado em bitmap, semeado com as regiões usáveis do memory map
+//o bitmap fica em frames físicos da própria região usável (acessados pelo desvio
+//da memória física), então ele não depende do heap que ajuda a construir
+
+const FRAME_SIZE: u64 = Size4KiB::SIZE;
+const BITS_PER_WORD: usize = u64::BITS as usize;
 
-extern crate alloc;
-use alloc::vec::Vec;
 pub struct BootInfoFrameAllocator {
     pub memory_map: &'static MemoryMap,
-    pub free_frames: Vec<PhysFrame<Size4KiB>>,
-    _next: usize,
+    // 1 bit por frame físico, 1 = ocupado ou inexistente, 0 = livre
+    bitmap: &'static mut [u64],
+    total_frames: usize,
+    used_frames: usize,
+    // frames livres prometidos para páginas sob demanda que ainda não foram tocadas; só
+    // allocate_reserved entrega estes, os outros pedidos veem menos frames livres
+    reserved_frames: usize,
+    // palavra do bitmap onde a próxima busca começa
+    next: usize,
 }
 
 impl BootInfoFrameAllocator {
     //cria um frame alocattor para o memory map
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        BootInfoFrameAllocator {
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        // o bitmap precisa cobrir até o último frame usável
+        let frame_count = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| (r.range.end_addr() / FRAME_SIZE) as usize)
+            .max()
+            .unwrap_or(0);
+        let words = (frame_count + BITS_PER_WORD - 1) / BITS_PER_WORD;
+        let bitmap_bytes = (words * core::mem::size_of::<u64>()) as u64;
+        let bitmap_frames = (bitmap_bytes + FRAME_SIZE - 1) / FRAME_SIZE;
+
+        // procura a primeira região usável que comporte o bitmap
+        let bitmap_start = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= bitmap_frames * FRAME_SIZE)
+            .map(|r| r.range.start_addr())
+            .expect("no usable region large enough for the frame bitmap");
+
+        let virt = physical_memory_offset + bitmap_start;
+        let bitmap = core::slice::from_raw_parts_mut(virt.as_mut_ptr::<u64>(), words);
+
+        // tudo começa ocupado, apenas os frames usáveis são liberados
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = BootInfoFrameAllocator {
             memory_map,
-            free_frames: Vec::new(),
-            _next: 0,
+            bitmap,
+            total_frames: 0,
+            used_frames: 0,
+            reserved_frames: 0,
+            next: 0,
+        };
+
+        for frame in allocator.usable_frames() {
+            allocator.clear_bit(Self::frame_index(frame));
+            allocator.total_frames += 1;
+        }
+
+        // reserva os frames onde o próprio bitmap está guardado
+        for i in 0..bitmap_frames {
+            let frame = PhysFrame::containing_address(PhysAddr::new(bitmap_start + i * FRAME_SIZE));
+            allocator.set_bit(Self::frame_index(frame));
+            allocator.used_frames += 1;
         }
+
+        allocator
     }
 
     // retorna um iterador de frames usados pelo memory map
-    fn _usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
+    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
         //consegue as regiões
         let regions = self.memory_map.iter();
         let usable_regions = regions
@@ -69,73 +121,470 @@ impl BootInfoFrameAllocator {
         frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
     }
 
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    pub fn used_frames(&self) -> usize {
+        self.used_frames
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.total_frames - self.used_frames
+    }
+
+    pub fn reserved_frames(&self) -> usize {
+        self.reserved_frames
+    }
+
+    // separa `count` frames livres para allocate_reserved; false se não há tantos sem dono
+    pub fn reserve(&mut self, count: usize) -> bool {
+        if self.free_frames() - self.reserved_frames < count {
+            return false;
+        }
+        self.reserved_frames += count;
+        true
+    }
+
+    // desfaz uma reserva que não vai mais ser usada
+    pub fn unreserve(&mut self, count: usize) {
+        self.reserved_frames = self.reserved_frames.saturating_sub(count);
+    }
+
+    // entrega um dos frames separados por reserve, None se não sobrou reserva
+    pub fn allocate_reserved(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        if self.reserved_frames == 0 {
+            return None;
+        }
+        self.reserved_frames -= 1;
+        self.take_frame()
+    }
+
+    fn frame_index(frame: PhysFrame<Size4KiB>) -> usize {
+        (frame.start_address().as_u64() / FRAME_SIZE) as usize
+    }
+
+    fn set_bit(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+    }
+
+    fn clear_bit(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+    }
+
     // Verifica se um frame está livre
     fn is_frame_free(&self, frame: PhysFrame<Size4KiB>) -> bool {
-        // Percorre a lista de frames livres e verifica se o frame fornecido está nela
-        for free_frame in &self.free_frames {
-            if *free_frame == frame {
-                return true;
-            }
+        let index = Self::frame_index(frame);
+        match self.bitmap.get(index / BITS_PER_WORD) {
+            Some(word) => word & (1 << (index % BITS_PER_WORD)) == 0,
+            None => false,
         }
-        false
     }
 
-    // Marca um frame como alocado
-    fn mark_frame_allocated(&mut self, frame: PhysFrame<Size4KiB>) {
-        // Remove o frame da lista de frames livres
-        self.free_frames.retain(|&free_frame| free_frame != frame);
+    // Verifica se o frame pertence a alguma região usável do memory map
+    fn is_usable(&self, frame: PhysFrame<Size4KiB>) -> bool {
+        let addr = frame.start_address().as_u64();
+        self.memory_map.iter().any(|r| {
+            r.region_type == MemoryRegionType::Usable
+                && addr >= r.range.start_addr()
+                && addr < r.range.end_addr()
+        })
     }
 }
 
 use x86_64::structures::paging::FrameAllocator;
 use x86_64::structures::paging::PageSize;
+use x86_64::structures::paging::Size2MiB;
+
+// um frame de 2 MiB são 512 frames de 4 KiB, ou 8 palavras inteiras do bitmap
+const HUGE_FRAME_WORDS: usize = (Size2MiB::SIZE / FRAME_SIZE) as usize / BITS_PER_WORD;
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        // Itera sobre as regiões de memória do boot info
-        for region in self.memory_map.iter() {
-            // Obtém o início e o fim da região de memória
-            let region_start = region.range.start_addr();
-            let region_end = region.range.end_addr();
-    
-            // Itera sobre os endereços dentro da região de memória
-            let size = Size4KiB::SIZE as u64;
-            for address in (region_start..region_end).step_by(size as usize) {
-                // Verifica se o endereço está dentro de uma página de 4 KiB
-                let addr = PhysAddr::new(address);
-                let frame = PhysFrame::containing_address(addr);
-                // Verifica se o frame está livre
-                if self.is_frame_free(frame) {
-                    // Marca o frame como ocupado e retorna
-                    self.mark_frame_allocated(frame);
-                    return Some(frame);
-                }
+        if self.free_frames() <= self.reserved_frames {
+            return None;
+        }
+        self.take_frame()
+    }
+}
+
+impl BootInfoFrameAllocator {
+    fn take_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let words = self.bitmap.len();
+
+        // começa a busca pela dica e dá no máximo uma volta no bitmap,
+        // pulando palavras inteiras sem nenhum bit livre
+        for i in 0..words {
+            let word_index = (self.next + i) % words;
+            let word = self.bitmap[word_index];
+            if word == u64::MAX {
+                continue;
             }
+
+            let index = word_index * BITS_PER_WORD + word.trailing_ones() as usize;
+            self.set_bit(index);
+            self.used_frames += 1;
+            self.next = word_index;
+
+            let addr = PhysAddr::new(index as u64 * FRAME_SIZE);
+            return Some(PhysFrame::containing_address(addr));
         }
         None // Retorna None se nenhum frame livre for encontrado
     }
 }
 
+//frames de 2 MiB: 8 palavras livres seguidas, começando numa palavra múltipla de 8
+//(o que alinha o endereço físico em 2 MiB)
+unsafe impl FrameAllocator<Size2MiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        if self.free_frames() - self.reserved_frames < HUGE_FRAME_WORDS * BITS_PER_WORD {
+            return None;
+        }
+        let first = (0..self.bitmap.len() / HUGE_FRAME_WORDS)
+            .map(|run| run * HUGE_FRAME_WORDS)
+            .find(|&start| self.bitmap[start..start + HUGE_FRAME_WORDS].iter().all(|&word| word == 0))?;
+
+        self.bitmap[first..first + HUGE_FRAME_WORDS].fill(u64::MAX);
+        self.used_frames += HUGE_FRAME_WORDS * BITS_PER_WORD;
+
+        let addr = PhysAddr::new((first * BITS_PER_WORD) as u64 * FRAME_SIZE);
+        Some(PhysFrame::containing_address(addr))
+    }
+}
+
 use x86_64::structures::paging::FrameDeallocator;
 
 impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
     unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
-        // Encontrar a página física correspondente ao frame
-        let phys_addr = frame.start_address();
-        
-        // Procurar na lista de regiões de memória do boot info pela região que contém este endereço físico
-        for region in self.memory_map.iter() {
-            let region_start = PhysAddr::new(region.range.start_addr());
-            let region_end = PhysAddr::new(region.range.end_addr());
-
-            if phys_addr >= region_start && phys_addr < region_end {
-                // Se encontrarmos a região, devolvemos o frame à lista de frames livres
-                self.free_frames.push(frame);
-                return;
+        // frames fora das regiões usáveis nunca foram entregues por este alocador
+        if !self.is_usable(frame) {
+            crate::println!("Aviso: Tentativa de desalocação de um frame que não está em nenhuma região de memória conhecida.");
+            return;
+        }
+
+        if self.is_frame_free(frame) {
+            panic!("double free of physical frame {:?}", frame.start_address());
+        }
+
+        let index = Self::frame_index(frame);
+        self.clear_bit(index);
+        self.used_frames -= 1;
+        // frames recém liberados ficam perto do começo da próxima busca
+        self.next = self.next.min(index / BITS_PER_WORD);
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let first = (frame.start_address().as_u64() / FRAME_SIZE) as usize / BITS_PER_WORD;
+        let words = &mut self.bitmap[first..first + HUGE_FRAME_WORDS];
+        if words.iter().any(|&word| word != u64::MAX) {
+            panic!("double free of physical frame {:?}", frame.start_address());
+        }
+
+        words.fill(0);
+        self.used_frames -= HUGE_FRAME_WORDS * BITS_PER_WORD;
+        self.next = self.next.min(first);
+    }
+}
+
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::structures::paging::mapper::MapToError;
+use spin::Mutex;
+use crate::combined_allocator::{HEAP_MAX_SIZE, HEAP_SIZE, HEAP_START};
+
+//o mapeador e o alocador de frames ficam globais para que o heap possa crescer
+//de dentro do alocador, nenhum dos dois pode usar o heap. o contrário também vale: com
+//um destes locks pego nada pode tocar o heap, a primeira leitura de uma página dele cai
+//no handler de page fault, que precisa dos dois (vmm::handle_demand_fault dá panic)
+pub static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+pub static FRAME_ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub total_frames: usize,
+    pub used_frames: usize,
+    pub free_frames: usize,
+    // dos livres, quantos já estão prometidos para páginas do heap ainda não tocadas
+    pub reserved_frames: usize,
+}
+
+//contadores do alocador de frames, None antes de init_heap
+pub fn frame_stats() -> Option<FrameStats> {
+    //o heap pega este lock para crescer, inclusive dentro de um handler de interrupção
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        FRAME_ALLOCATOR.lock().as_ref().map(|frames| FrameStats {
+            total_frames: frames.total_frames(),
+            used_frames: frames.used_frames(),
+            free_frames: frames.free_frames(),
+            reserved_frames: frames.reserved_frames(),
+        })
+    })
+}
+
+//desvio onde o bootloader mapeou a memória física, None antes de init_heap
+pub fn physical_memory_offset() -> Option<VirtAddr> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        MAPPER.lock().as_ref().map(|mapper| mapper.phys_offset())
+    })
+}
+
+//guarda o mapeador e o alocador de frames, reserva a janela do heap (paginada sob demanda)
+//e libera o CombinedAllocator para uso
+pub fn init_heap(
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BootInfoFrameAllocator,
+) -> Result<(), MapToError<Size4KiB>> {
+    use crate::address_space::{USER_END, USER_START};
+    use crate::vmm::{self, RegionKind, KERNEL_VMA_SIZE, KERNEL_VMA_START};
+
+    // o bootloader mapeia toda a memória física a partir do desvio
+    let physical_map_size = frame_allocator
+        .memory_map
+        .iter()
+        .map(|r| r.range.end_addr())
+        .max()
+        .unwrap_or(0);
+    let physical_map_size = (physical_map_size + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+    vmm::reserve(mapper.phys_offset(), physical_map_size, RegionKind::PhysicalMap, "physical map")
+        .expect("physical memory map overlaps another region");
+    let heap = vmm::reserve(VirtAddr::new(HEAP_START as u64), HEAP_MAX_SIZE as u64, RegionKind::Heap, "heap")
+        .expect("heap window overlaps another region");
+    vmm::set_demand_paged(heap, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE);
+    vmm::reserve(VirtAddr::new(USER_START), USER_END - USER_START, RegionKind::User, "user space")
+        .expect("user window overlaps another region");
+
+    let physical_map = mapper.phys_offset();
+    *MAPPER.lock() = Some(mapper);
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+    // daqui para frente todo mapeamento novo já sai com NO_EXECUTE
+    protect_kernel(physical_map, physical_map_size);
+    vmm::account_huge_pages(physical_map, physical_map_size);
+    // o heap e a janela do vmm ganham mapeamentos depois do boot, os espaços de endereçamento
+    // dos processos precisam enxergar
+    vmm::share_level_4_entries(heap, HEAP_MAX_SIZE as u64)?;
+    vmm::share_level_4_entries(VirtAddr::new(KERNEL_VMA_START), KERNEL_VMA_SIZE)?;
+
+    map_heap_range(HEAP_START, HEAP_SIZE)?;
+
+    // o alocador só pode entregar endereços depois que a janela aceitar page faults
+    #[cfg(feature = "allocator-combined")]
+    unsafe { crate::combined_allocator::ALLOCATOR.init() };
+    #[cfg(feature = "allocator-buddy")]
+    unsafe { crate::buddy_allocator::ALLOCATOR.init() };
+
+    // com o mapeador no lugar as pilhas podem sair do vmm
+    crate::stack::init();
+
+    Ok(())
+}
+
+//prepara [start, start + size) para o heap. os pedaços de 2 MiB alinhados já saem mapeados
+//como páginas grandes; o resto é mapeado no primeiro acesso (vmm::handle_demand_fault), mas
+//as tabelas e um frame por página são separados aqui. falha se não há frames para isso,
+//assim o heap segue o caminho de falta de memória em vez de morrer num page fault mais tarde
+pub fn map_heap_range(start: usize, size: usize) -> Result<(), MapToError<Size4KiB>> {
+    let (start, end) = (VirtAddr::new(start as u64), VirtAddr::new((start + size) as u64));
+    let huge_start = start.align_up(Size2MiB::SIZE).min(end);
+    let huge_end = end.align_down(Size2MiB::SIZE).max(huge_start);
+    if huge_end > huge_start {
+        // o heap conta com páginas novas zeradas (alloc_zeroed não zera de novo)
+        crate::vmm::map_range(huge_start, huge_end - huge_start, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE)?;
+    }
+    crate::vmm::commit_demand_range(start, huge_start - start)?;
+    crate::vmm::commit_demand_range(huge_end, end - huge_end)
+}
+
+//janelas de mmio para drivers: memória de dispositivo mapeada sem cache no espaço
+//virtual do kernel. os frames são do dispositivo, então iounmap só desfaz o mapeamento
+
+use core::marker::PhantomData;
+
+pub struct Mmio<T> {
+    // início do intervalo reservado no vmm, alinhado a página
+    region: VirtAddr,
+    size: u64,
+    // onde o endereço físico pedido cai dentro da primeira página
+    base: VirtAddr,
+    len: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T: Copy> Mmio<T> {
+    pub fn as_ptr(&self) -> *mut T {
+        self.base.as_mut_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn read(&self) -> T {
+        unsafe { core::ptr::read_volatile(self.as_ptr()) }
+    }
+
+    pub fn write(&mut self, value: T) {
+        unsafe { core::ptr::write_volatile(self.as_ptr(), value) }
+    }
+
+    // registrador de tipo `R` a `offset` bytes do começo da janela
+    pub fn read_at<R: Copy>(&self, offset: usize) -> R {
+        unsafe { core::ptr::read_volatile(self.register::<R>(offset)) }
+    }
+
+    pub fn write_at<R: Copy>(&mut self, offset: usize, value: R) {
+        unsafe { core::ptr::write_volatile(self.register::<R>(offset), value) }
+    }
+
+    fn register<R>(&self, offset: usize) -> *mut R {
+        assert!(
+            offset + core::mem::size_of::<R>() <= self.len,
+            "mmio access at {:#x} past the end of a {:#x} byte window", offset, self.len
+        );
+        let addr = self.base + offset as u64;
+        assert!(addr.is_aligned(core::mem::align_of::<R>() as u64), "unaligned mmio access at {:#x}", offset);
+        addr.as_mut_ptr()
+    }
+}
+
+//mapeia `len` bytes de memória de dispositivo a partir de `phys` com NO_CACHE e WRITE_THROUGH
+//
+//unsafe: `phys` precisa ser memória de dispositivo (ou reservada), não um frame que o
+//alocador de frames possa entregar, e `T` precisa caber em `len`
+pub unsafe fn ioremap<T: Copy>(phys: PhysAddr, len: usize, name: &'static str) -> Result<Mmio<T>, MapToError<Size4KiB>> {
+    use crate::vmm::{self, RegionKind};
+
+    assert!(core::mem::size_of::<T>() <= len, "mmio window smaller than its type");
+    let start = phys.align_down(FRAME_SIZE);
+    let size = (phys + len as u64).align_up(FRAME_SIZE) - start;
+
+    // uma janela grande fica na mesma posição dentro de 2 MiB que o endereço físico, assim os
+    // pedaços alinhados podem virar páginas grandes
+    let (lead, align) = if size >= Size2MiB::SIZE {
+        (start.as_u64() % Size2MiB::SIZE, Size2MiB::SIZE)
+    } else {
+        (0, FRAME_SIZE)
+    };
+    let region = vmm::allocate(lead + size, align, RegionKind::Mmio, name)
+        .map_err(|_| MapToError::FrameAllocationFailed)?;
+    let flags = PageTableFlags::WRITABLE
+        | PageTableFlags::NO_EXECUTE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH;
+
+    let mapping = region + lead;
+    if let Err(err) = vmm::map_device_range(mapping, start, size, flags) {
+        vmm::release(region);
+        return Err(err);
+    }
+
+    Ok(Mmio {
+        region,
+        size: lead + size,
+        base: mapping + (phys - start),
+        len,
+        _marker: PhantomData,
+    })
+}
+
+//desfaz um ioremap, o endereço virtual volta para o vmm
+pub fn iounmap<T>(mmio: Mmio<T>) {
+    unsafe { crate::vmm::unmap_device_range(mmio.region, mmio.size) };
+    crate::vmm::release(mmio.region);
+}
+
+//W^X: depois do boot nenhuma página do kernel é gravável e executável ao mesmo tempo.
+//as permissões de cada parte da imagem vêm dos program headers do ELF, que o lld deixa
+//carregados junto com o primeiro segmento (__ehdr_start)
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+// tamanho de um Elf64_Phdr; a imagem tem uns poucos segmentos, mais do que isto é lixo
+const PHDR_SIZE: usize = 56;
+const MAX_PHDRS: usize = 16;
+
+extern "C" {
+    static __ehdr_start: u8;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub start: VirtAddr,
+    pub size: u64,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+//segmentos PT_LOAD da imagem do kernel: .text, .rodata e .data/.bss
+pub fn kernel_segments(mut f: impl FnMut(Segment)) {
+    unsafe {
+        let ehdr = core::ptr::addr_of!(__ehdr_start);
+        // se o linker não deixou o cabeçalho carregado, __ehdr_start aponta para outra coisa
+        // e os campos abaixo seriam lidos de lixo
+        let ident = core::slice::from_raw_parts(ehdr, 5);
+        assert!(
+            ident == b"\x7fELF\x02",
+            "__ehdr_start does not point to a loaded ELF64 header (found {:x?})", ident
+        );
+        let read_u16 = |offset: usize| (ehdr.add(offset) as *const u16).read_unaligned() as usize;
+        let phoff = (ehdr.add(32) as *const u64).read_unaligned() as usize;
+        let (phentsize, phnum) = (read_u16(54), read_u16(56));
+        assert!(
+            phentsize == PHDR_SIZE && (1..=MAX_PHDRS).contains(&phnum),
+            "kernel ELF header has {} program headers of {} bytes, expected 1..={} of {}",
+            phnum, phentsize, MAX_PHDRS, PHDR_SIZE
+        );
+        // os program headers são carregados na mesma página do cabeçalho
+        assert!(
+            phoff + phnum * phentsize <= 4096,
+            "kernel program headers at offset {:#x} are outside the loaded header page", phoff
+        );
+
+        for i in 0..phnum {
+            let ph = ehdr.add(phoff + i * phentsize);
+            if (ph as *const u32).read_unaligned() != PT_LOAD {
+                continue;
             }
+            let flags = (ph.add(4) as *const u32).read_unaligned();
+            f(Segment {
+                start: VirtAddr::new((ph.add(16) as *const u64).read_unaligned()),
+                size: (ph.add(40) as *const u64).read_unaligned(),
+                writable: flags & PF_W != 0,
+                executable: flags & PF_X != 0,
+            });
         }
+    }
+}
+
+//liga EFER.NXE e CR0.WP e refaz as permissões da imagem do kernel e do mapa da memória física
+fn protect_kernel(physical_map: VirtAddr, physical_map_size: u64) {
+    use x86_64::registers::control::{Cr0, Cr0Flags};
+    use x86_64::registers::model_specific::{Efer, EferFlags};
 
-        // Se o frame não estiver contido em nenhuma região, imprima um aviso
-        crate::println!("Aviso: Tentativa de desalocação de um frame que não está em nenhuma região de memória conhecida.");
+    unsafe {
+        // sem NXE o bit NO_EXECUTE é reservado e qualquer página com ele dá page fault
+        Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+        // sem WP o kernel escreve por cima de páginas só de leitura
+        Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
     }
+
+    kernel_segments(|segment| {
+        if segment.writable && segment.executable {
+            panic!("kernel segment at {:?} is writable and executable", segment.start);
+        }
+        let flags = match (segment.writable, segment.executable) {
+            (_, true) => PageTableFlags::PRESENT,
+            (true, false) => PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+            (false, false) => PageTableFlags::NO_EXECUTE,
+        };
+        crate::vmm::set_flags(segment.start, segment.size, flags);
+    });
+
+    crate::vmm::set_flags(physical_map, physical_map_size, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE);
 }
\ No newline at end of file
@@ -0,0 +1,70 @@
+//caminho de falta de memória
+//quando o alocador não consegue atender um pedido, ele já devolveu o que tinha em
+//cache e chama `handle`: os callbacks registrados pelos subsistemas têm a chance de
+//soltar memória e o pedido é tentado de novo; se nada resolver o layout e o estado do
+//heap e dos frames vão para a serial antes do null voltar para quem pediu
+
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::serial_println;
+
+const MAX_CALLBACKS: usize = 8;
+
+// cada callback recebe o pedido que falhou e devolve true se liberou alguma coisa
+pub type OomCallback = fn(Layout) -> bool;
+
+static CALLBACKS: Mutex<[Option<OomCallback>; MAX_CALLBACKS]> = Mutex::new([None; MAX_CALLBACKS]);
+
+// um callback que aloca e falha de novo não pode entrar nos callbacks outra vez
+static HANDLING: AtomicBool = AtomicBool::new(false);
+
+// devolve o número usado para tirar o callback depois, None se a tabela está cheia
+pub fn register(callback: OomCallback) -> Option<usize> {
+    without_interrupts(|| {
+        let mut callbacks = CALLBACKS.lock();
+        let id = callbacks.iter().position(|slot| slot.is_none())?;
+        callbacks[id] = Some(callback);
+        Some(id)
+    })
+}
+
+pub fn unregister(id: usize) {
+    without_interrupts(|| CALLBACKS.lock()[id] = None);
+}
+
+// roda os callbacks sem o lock pego, eles vão liberar memória pelo próprio alocador
+fn run_callbacks(layout: Layout) -> bool {
+    let callbacks = without_interrupts(|| *CALLBACKS.lock());
+    let mut released = false;
+    for callback in callbacks.iter().flatten() {
+        released |= callback(layout);
+    }
+    released
+}
+
+// escreve na serial o pedido que falhou e o estado da memória
+pub fn report(layout: Layout) {
+    serial_println!("out of memory: size {} align {}", layout.size(), layout.align());
+    crate::meminfo::report_serial();
+}
+
+// chamado pelo alocador depois que o pedido falhou mesmo com os caches devolvidos;
+// `retry` refaz o pedido e o resultado dele é o que o alocador deve devolver
+pub fn handle(layout: Layout, mut retry: impl FnMut() -> *mut u8) -> *mut u8 {
+    if !HANDLING.swap(true, Ordering::SeqCst) {
+        let released = run_callbacks(layout);
+        HANDLING.store(false, Ordering::SeqCst);
+        if released {
+            let ptr = retry();
+            if !ptr.is_null() {
+                return ptr;
+            }
+        }
+    }
+
+    report(layout);
+    core::ptr::null_mut()
+}
@@ -0,0 +1,146 @@
+//inspeção das tabelas de página ativas
+//percorre os 4 níveis a partir do CR3 lendo cada tabela pelo desvio da memória física
+//e junta páginas vizinhas (virtual e físico contíguos, mesmas flags) em um intervalo só
+
+use core::fmt;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::memory;
+use crate::serial::SERIAL1;
+
+// flags que valem a pena mostrar; ACCESSED e DIRTY mudam sozinhas e quebrariam os intervalos,
+// HUGE_PAGE já aparece no tamanho
+const SHOWN_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::USER_ACCESSIBLE)
+    .union(PageTableFlags::WRITE_THROUGH)
+    .union(PageTableFlags::NO_CACHE)
+    .union(PageTableFlags::GLOBAL)
+    .union(PageTableFlags::NO_EXECUTE);
+
+// tamanho coberto por uma entrada em cada nível (índice 0 = P1)
+const ENTRY_SIZE: [u64; 4] = [1 << 12, 1 << 21, 1 << 30, 1 << 39];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub virt: VirtAddr,
+    pub phys: PhysAddr,
+    pub size: u64,
+    // flags efetivas: WRITABLE e USER só se todos os níveis deixam, NO_EXECUTE se algum nível pede
+    pub flags: PageTableFlags,
+}
+
+impl Mapping {
+    fn extends(&self, next: &Mapping) -> bool {
+        // conta em u64 puro, o fim da metade de baixo não é um endereço canônico
+        self.virt.as_u64().wrapping_add(self.size) == next.virt.as_u64()
+            && self.phys.as_u64() + self.size == next.phys.as_u64()
+            && self.flags == next.flags
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |flag, name| if self.flags.contains(flag) { name } else { "-" };
+        write!(
+            f,
+            "{:#018x}-{:#018x} -> {:#012x} {:>9} KiB {} {} {}",
+            self.virt.as_u64(),
+            self.virt.as_u64().wrapping_add(self.size),
+            self.phys.as_u64(),
+            self.size / 1024,
+            flag(PageTableFlags::WRITABLE, "WRITABLE"),
+            flag(PageTableFlags::NO_EXECUTE, "NO_EXECUTE"),
+            flag(PageTableFlags::USER_ACCESSIBLE, "USER"),
+        )
+    }
+}
+
+// combina as flags de um nível com as dos níveis acima
+fn inherit(parent: PageTableFlags, entry: PageTableFlags) -> PageTableFlags {
+    let restrictive = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let entry = entry & SHOWN_FLAGS;
+    (entry & !restrictive) | (entry & parent & restrictive) | (parent & PageTableFlags::NO_EXECUTE)
+}
+
+unsafe fn walk_table(
+    physical_memory_offset: VirtAddr,
+    table: &PageTable,
+    level: usize,
+    base: u64,
+    parent: PageTableFlags,
+    f: &mut impl FnMut(Mapping),
+) {
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+
+        // sign extension dos endereços da metade de cima
+        let virt = VirtAddr::new_truncate(base + index as u64 * ENTRY_SIZE[level - 1]);
+        let flags = inherit(parent, flags);
+        if level == 1 || entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            f(Mapping { virt, phys: entry.addr(), size: ENTRY_SIZE[level - 1], flags });
+        } else {
+            let next = &*(physical_memory_offset + entry.addr().as_u64()).as_ptr::<PageTable>();
+            walk_table(physical_memory_offset, next, level - 1, virt.as_u64(), flags, f);
+        }
+    }
+}
+
+// chama `f` com cada intervalo mapeado, já juntado e em ordem de endereço virtual
+//
+// unsafe: toda a memória física precisa estar mapeada em `physical_memory_offset`
+pub unsafe fn walk(physical_memory_offset: VirtAddr, mut f: impl FnMut(Mapping)) {
+    let level_4_table = memory::active_level_4_table(physical_memory_offset);
+    let all = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+    let mut pending: Option<Mapping> = None;
+    walk_table(physical_memory_offset, level_4_table, 4, 0, all, &mut |mapping| {
+        match pending.as_mut() {
+            Some(current) if current.extends(&mapping) => current.size += mapping.size,
+            _ => {
+                if let Some(done) = pending.replace(mapping) {
+                    f(done);
+                }
+            }
+        }
+    });
+    if let Some(done) = pending {
+        f(done);
+    }
+}
+
+fn write_report(out: &mut impl fmt::Write, physical_memory_offset: VirtAddr) -> fmt::Result {
+    writeln!(out, "page map:")?;
+    let mut result = Ok(());
+    let mut total = 0;
+    unsafe {
+        walk(physical_memory_offset, |mapping| {
+            total += mapping.size;
+            if result.is_ok() {
+                result = writeln!(out, "  {}", mapping);
+            }
+        });
+    }
+    result?;
+    writeln!(out, "  {} KiB mapped", total / 1024)
+}
+
+// só na serial, o mapa inteiro não cabe no vga
+pub fn report_serial() {
+    let offset = match memory::physical_memory_offset() {
+        Some(offset) => offset,
+        None => {
+            crate::serial_println!("page map: memory not initialized");
+            return;
+        }
+    };
+    // o heap não pode crescer (e mexer nas tabelas) no meio da leitura
+    interrupts::without_interrupts(|| {
+        write_report(&mut *SERIAL1.lock(), offset).expect("Printing to serial failed");
+    });
+}
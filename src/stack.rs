@@ -0,0 +1,131 @@
+//pilhas do kernel
+//cada pilha é um intervalo do vmm cuja primeira página fica sem mapeamento; um estouro
+//cai nessa página e o handler de page fault (que roda na sua própria pilha IST)
+//descobre pelo vmm de qual pilha ela é. a IST1 não aguenta um page fault dentro do
+//handler, ver interrupts::IN_PAGE_FAULT
+
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{PageTableFlags, Size4KiB, Translate};
+use x86_64::VirtAddr;
+
+use crate::gdt;
+use crate::memory::MAPPER;
+use crate::vmm::{self, RegionKind};
+
+pub const GUARD_SIZE: u64 = 4096;
+pub const DEFAULT_STACK_SIZE: u64 = 5 * 4096;
+
+// até onde procurar as bordas da pilha que o bootloader montou (o padrão do bootloader 0.9
+// é kernel-stack-size = 512 páginas)
+const BOOT_STACK_SCAN: u64 = 1024;
+
+#[derive(Debug)]
+pub struct KernelStack {
+    // começo do intervalo no vmm, é a página de guarda
+    region: VirtAddr,
+    size: u64,
+    name: &'static str,
+}
+
+impl KernelStack {
+    // a pilha cresce para baixo, este é o valor inicial do rsp
+    pub fn top(&self) -> VirtAddr {
+        self.bottom() + self.size
+    }
+
+    pub fn bottom(&self) -> VirtAddr {
+        self.region + GUARD_SIZE
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+// reserva `size` bytes de pilha (arredondado para páginas) mais a página de guarda e mapeia a pilha
+pub fn allocate(size: u64, name: &'static str) -> Result<KernelStack, MapToError<Size4KiB>> {
+    let size = (size + GUARD_SIZE - 1) & !(GUARD_SIZE - 1);
+    let region = vmm::allocate(GUARD_SIZE + size, GUARD_SIZE, RegionKind::Stack, name)
+        .map_err(|_| MapToError::FrameAllocationFailed)?;
+
+    let stack = KernelStack { region, size, name };
+    if let Err(err) = vmm::map_range(stack.bottom(), size, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE) {
+        unsafe { vmm::unmap_range(stack.bottom(), size) };
+        vmm::release(region);
+        return Err(err);
+    }
+    Ok(stack)
+}
+
+// unsafe: a pilha não pode estar em uso (nem ser o rsp de ninguém, nem estar na TSS)
+pub unsafe fn free(stack: KernelStack) {
+    vmm::unmap_range(stack.bottom(), stack.size);
+    vmm::release(stack.region);
+}
+
+// o nome da pilha se `addr` cai na página de guarda de alguma, usado pelo handler de page fault
+pub fn guard_hit(addr: VirtAddr) -> Option<&'static str> {
+    let region = vmm::try_find(addr)?;
+    if region.kind == RegionKind::Stack && addr < region.start + GUARD_SIZE {
+        Some(region.name)
+    } else {
+        None
+    }
+}
+
+// registra a pilha de boot no vmm: o bootloader deixa uma página sem mapeamento abaixo
+// dela, então basta achar onde a faixa mapeada em volta do rsp começa e termina. sem essa
+// guarda um estouro da pilha de boot escreveria em silêncio no que vier embaixo, então
+// qualquer coisa fora do esperado é panic
+fn reserve_boot_stack() {
+    let rsp: u64;
+    unsafe { core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack)) };
+    let rsp_page = rsp & !(GUARD_SIZE - 1);
+
+    let (start, end) = x86_64::instructions::interrupts::without_interrupts(|| {
+        let mapper = MAPPER.lock();
+        let mapper = mapper.as_ref().expect("boot stack guard needs the mapper");
+        let mapped = |page: u64| mapper.translate_addr(VirtAddr::new_truncate(page)).is_some();
+
+        let mut bottom = rsp_page;
+        while mapped(bottom - GUARD_SIZE) {
+            bottom -= GUARD_SIZE;
+            assert!(
+                rsp_page - bottom < BOOT_STACK_SCAN * GUARD_SIZE,
+                "no unmapped guard page within {} pages below the boot stack at {:#x}", BOOT_STACK_SCAN, rsp
+            );
+        }
+        let mut top = rsp_page + GUARD_SIZE;
+        while mapped(top) {
+            top += GUARD_SIZE;
+            assert!(
+                top - rsp_page < BOOT_STACK_SCAN * GUARD_SIZE,
+                "boot stack at {:#x} has no end within {} pages", rsp, BOOT_STACK_SCAN
+            );
+        }
+        (bottom - GUARD_SIZE, top)
+    });
+
+    if let Err(err) = vmm::reserve(VirtAddr::new(start), end - start, RegionKind::Stack, "boot stack") {
+        panic!("boot stack {:#x}..{:#x} could not be registered: {:?}", start, end, err);
+    }
+    let bottom = VirtAddr::new(start + GUARD_SIZE);
+    vmm::set_flags(bottom, end - bottom.as_u64(), PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE);
+}
+
+//troca as pilhas IST estáticas do boot por pilhas com guarda e registra a pilha de boot;
+//chamado por memory::init_heap, depois que o mapeador existe
+pub(crate) fn init() {
+    reserve_boot_stack();
+
+    let ist = [
+        (gdt::DOUBLE_FAULT_IST_INDEX, "double fault stack"),
+        (gdt::PAGE_FAULT_IST_INDEX, "page fault stack"),
+    ];
+    for (index, name) in ist {
+        let stack = allocate(DEFAULT_STACK_SIZE, name).expect("IST stack allocation failed");
+        gdt::set_ist_stack(index, stack.top());
+        // a pilha fica na TSS até o fim
+        core::mem::forget(stack);
+    }
+}
@@ -0,0 +1,596 @@
+//gerente do espaço virtual do kernel
+//guarda quais intervalos já têm dono (heap, mapa da memória física, pilhas, janelas de
+//mmio) e entrega intervalos novos de uma janela própria, sempre com uma página sem
+//mapeamento entre vizinhos para que um estouro caia num page fault e não no vizinho.
+//intervalos com paginação sob demanda (o heap) só ganham frames no primeiro acesso.
+//a tabela tem tamanho fixo, ela é usada para montar o próprio heap
+
+use gale_heap::FixedVec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use x86_64::structures::paging::mapper::{FlagUpdateError, MapToError, UnmapError};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, Page, PageSize, PageTable, PageTableFlags, PhysFrame, Size2MiB,
+    Size4KiB, Translate,
+};
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::memory::{BootInfoFrameAllocator, FRAME_ALLOCATOR, MAPPER};
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+// janela de onde saem os intervalos de `allocate`, longe do heap e do que o bootloader usa
+pub const KERNEL_VMA_START: u64 = 0x_5555_0000_0000;
+pub const KERNEL_VMA_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+// espaço sem mapeamento deixado antes e depois de cada intervalo entregue
+pub const GUARD_GAP: u64 = PAGE_SIZE;
+
+const MAX_REGIONS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Heap,
+    PhysicalMap,
+    Stack,
+    Mmio,
+    // a janela dos espaços de endereçamento dos processos (address_space)
+    User,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: VirtAddr,
+    pub size: u64,
+    pub kind: RegionKind,
+    pub name: &'static str,
+    // com Some as páginas são mapeadas no primeiro acesso, com estas flags
+    pub demand: Option<PageTableFlags>,
+    // quantos bytes do começo já passaram por commit_demand_range; só eles têm frame reservado
+    pub committed: u64,
+    // frames reservados para páginas preparadas e ainda não tocadas, devolvidos por release
+    pub reserved: usize,
+}
+
+impl Region {
+    pub fn end(&self) -> u64 {
+        self.start.as_u64() + self.size
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr.as_u64() < self.end()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaError {
+    // o intervalo pedido encosta em outro que já tem dono
+    Overlap,
+    // não sobrou buraco do tamanho pedido na janela
+    NoSpace,
+    TableFull,
+    // tamanho zero, não múltiplo de página ou endereço não canônico
+    InvalidRange,
+}
+
+const EMPTY: Region = Region {
+    start: VirtAddr::zero(),
+    size: 0,
+    kind: RegionKind::Other,
+    name: "",
+    demand: None,
+    committed: 0,
+    reserved: 0,
+};
+
+// ordenada por endereço
+static REGIONS: Mutex<FixedVec<Region, MAX_REGIONS>> = Mutex::new(FixedVec::new(EMPTY));
+
+fn page_aligned(value: u64) -> bool {
+    value.is_multiple_of(PAGE_SIZE)
+}
+
+fn insert(regions: &mut FixedVec<Region, MAX_REGIONS>, region: Region) -> Result<VirtAddr, VmaError> {
+    if !regions.push(region) {
+        return Err(VmaError::TableFull);
+    }
+    regions.sort_unstable_by_key(|region| region.start);
+    Ok(region.start)
+}
+
+// marca um intervalo de endereço fixo como ocupado (heap, mapa físico, janelas conhecidas)
+pub fn reserve(start: VirtAddr, size: u64, kind: RegionKind, name: &'static str) -> Result<VirtAddr, VmaError> {
+    if size == 0 || !page_aligned(start.as_u64()) || !page_aligned(size) {
+        return Err(VmaError::InvalidRange);
+    }
+    let end = start.as_u64().checked_add(size).ok_or(VmaError::InvalidRange)?;
+
+    without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        if regions.iter().any(|r| start.as_u64() < r.end() && r.start.as_u64() < end) {
+            return Err(VmaError::Overlap);
+        }
+        insert(&mut regions, Region { start, size, kind, name, demand: None, committed: 0, reserved: 0 })
+    })
+}
+
+// entrega um intervalo livre da janela do kernel, alinhado e com GUARD_GAP dos dois lados;
+// o intervalo só fica reservado, mapear é com `map_range`
+pub fn allocate(size: u64, align: u64, kind: RegionKind, name: &'static str) -> Result<VirtAddr, VmaError> {
+    if size == 0 || !page_aligned(size) || !align.is_power_of_two() {
+        return Err(VmaError::InvalidRange);
+    }
+    let align = align.max(PAGE_SIZE);
+    let window_end = KERNEL_VMA_START + KERNEL_VMA_SIZE;
+
+    without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        // primeiro buraco que serve, andando pelos intervalos em ordem
+        let mut candidate = KERNEL_VMA_START + GUARD_GAP;
+        for region in regions.iter().filter(|r| r.end() > KERNEL_VMA_START && r.start.as_u64() < window_end) {
+            let start = (candidate + align - 1) & !(align - 1);
+            if start + size + GUARD_GAP <= region.start.as_u64() {
+                break;
+            }
+            candidate = candidate.max(region.end() + GUARD_GAP);
+        }
+
+        let start = (candidate + align - 1) & !(align - 1);
+        if start + size + GUARD_GAP > window_end {
+            return Err(VmaError::NoSpace);
+        }
+        insert(&mut regions, Region { start: VirtAddr::new(start), size, kind, name, demand: None, committed: 0, reserved: 0 })
+    })
+}
+
+// devolve o intervalo que começa em `start`, e a reserva das páginas que nunca foram tocadas;
+// as páginas dele precisam ter sido desmapeadas antes
+pub fn release(start: VirtAddr) -> Option<Region> {
+    without_interrupts(|| {
+        let region = {
+            let mut regions = REGIONS.lock();
+            let index = regions.iter().position(|r| r.start == start)?;
+            regions.remove(index)
+        };
+        if region.reserved > 0 {
+            if let Some(frame_allocator) = FRAME_ALLOCATOR.lock().as_mut() {
+                frame_allocator.unreserve(region.reserved);
+            }
+        }
+        Some(region)
+    })
+}
+
+// liga a paginação sob demanda no intervalo que começa em `start`: um acesso a uma página
+// ainda sem mapeamento ganha um frame zerado com `flags` em vez de ser um page fault fatal
+pub fn set_demand_paged(start: VirtAddr, flags: PageTableFlags) -> bool {
+    without_interrupts(|| {
+        match REGIONS.lock().iter_mut().find(|r| r.start == start) {
+            Some(region) => {
+                region.demand = Some(flags | PageTableFlags::PRESENT);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+// o intervalo que contém `addr`, se algum
+pub fn find(addr: VirtAddr) -> Option<Region> {
+    without_interrupts(|| REGIONS.lock().iter().find(|r| r.contains(addr)).copied())
+}
+
+// igual a `find`, mas desiste se a tabela estiver travada; para handlers de exceção, que
+// podem ter interrompido justamente quem segura o lock
+pub fn try_find(addr: VirtAddr) -> Option<Region> {
+    REGIONS.try_lock()?.iter().find(|r| r.contains(addr)).copied()
+}
+
+// chama `f` com cada intervalo, em ordem de endereço
+pub fn for_each(mut f: impl FnMut(Region)) {
+    let regions = without_interrupts(|| *REGIONS.lock());
+    for region in regions.iter() {
+        f(*region);
+    }
+}
+
+// páginas de 2 MiB mapeadas agora; cada uma ocupa uma entrada do TLB no lugar de 512
+static HUGE_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+const HUGE_PAGE_SIZE: u64 = Size2MiB::SIZE;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HugePageStats {
+    pub huge_pages: usize,
+    pub tlb_entries_saved: usize,
+}
+
+pub fn huge_page_stats() -> HugePageStats {
+    let huge_pages = HUGE_PAGES.load(Ordering::SeqCst);
+    HugePageStats {
+        huge_pages,
+        tlb_entries_saved: huge_pages * (HUGE_PAGE_SIZE / PAGE_SIZE - 1) as usize,
+    }
+}
+
+//soma às estatísticas as páginas de 2 MiB que já estavam mapeadas em [start, start + size),
+//como as do mapa da memória física que o bootloader monta
+pub fn account_huge_pages(start: VirtAddr, size: u64) {
+    use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+
+    let found = without_interrupts(|| {
+        let mapper = MAPPER.lock();
+        let mapper = match mapper.as_ref() {
+            Some(mapper) => mapper,
+            None => return 0,
+        };
+        let end = start.as_u64() + size;
+        let mut addr = start.align_down(HUGE_PAGE_SIZE).as_u64();
+        let mut found = 0;
+        while addr < end {
+            if let TranslateResult::Mapped { frame: MappedFrame::Size2MiB(_), .. } = mapper.translate(VirtAddr::new(addr)) {
+                found += 1;
+            }
+            addr += HUGE_PAGE_SIZE;
+        }
+        found
+    });
+    HUGE_PAGES.fetch_add(found, Ordering::SeqCst);
+}
+
+// páginas mapeadas pelo handler de page fault até agora
+static DEMAND_FAULTS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn demand_faults() -> usize {
+    DEMAND_FAULTS.load(Ordering::SeqCst)
+}
+
+//chamado pelo handler de page fault para uma página sem mapeamento: se ela é de um
+//intervalo com paginação sob demanda, mapeia um frame zerado e a instrução pode ser repetida.
+//o frame e as tabelas vêm de commit_demand_range; false quando o fault não é nosso ou cai
+//depois da parte preparada (acesso além do fim do heap)
+pub fn handle_demand_fault(addr: VirtAddr) -> bool {
+    // só há uma cpu, então um lock ocupado é de quem o fault interrompeu e esperar por ele
+    // trava para sempre; é a regra de memory::MAPPER sendo quebrada, melhor dizer onde
+    let mut regions = match REGIONS.try_lock() {
+        Some(regions) => regions,
+        None => panic!("page fault at {:?} while the vmm region table was locked", addr),
+    };
+    let region = match regions.iter_mut().find(|r| r.contains(addr)) {
+        Some(region) if region.demand.is_some() && addr < region.start + region.committed => region,
+        _ => return false,
+    };
+    let flags = region.demand.unwrap_or(PageTableFlags::PRESENT);
+
+    let (mut mapper, mut frame_allocator) = match (MAPPER.try_lock(), FRAME_ALLOCATOR.try_lock()) {
+        (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+        _ => panic!("demand-paged page {:?} touched while MAPPER or FRAME_ALLOCATOR was locked", addr),
+    };
+    let (mapper, frame_allocator) = match (mapper.as_mut(), frame_allocator.as_mut()) {
+        (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+        _ => return false,
+    };
+
+    let page = Page::<Size4KiB>::containing_address(addr);
+    let frame = match frame_allocator.allocate_reserved() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    // as tabelas já existem (commit_demand_range), map_to não pega frame nenhum
+    match unsafe { mapper.map_to(page, frame, flags, frame_allocator) } {
+        Ok(flush) => flush.flush(),
+        Err(err) => {
+            unsafe { FrameDeallocator::<Size4KiB>::deallocate_frame(frame_allocator, frame) };
+            // já mapeada não precisa mais da reserva; nos outros casos ela continua prometida
+            if matches!(err, MapToError::PageAlreadyMapped(_)) {
+                region.reserved = region.reserved.saturating_sub(1);
+                return true;
+            }
+            frame_allocator.reserve(1);
+            return false;
+        }
+    }
+    region.reserved = region.reserved.saturating_sub(1);
+    unsafe { core::ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, PAGE_SIZE as usize) };
+    DEMAND_FAULTS.fetch_add(1, Ordering::SeqCst);
+    true
+}
+
+// um pedaço de 2 MiB alinhado de [addr, end) pode virar uma página grande
+fn fits_huge_page(addr: u64, end: u64) -> bool {
+    addr.is_multiple_of(HUGE_PAGE_SIZE) && addr + HUGE_PAGE_SIZE <= end
+}
+
+//mapeia [start, start + size) em frames novos e zerados; pedaços de 2 MiB alinhados viram
+//páginas grandes quando há frames contíguos livres. páginas que já estão mapeadas são
+//mantidas para que uma tentativa que falhou no meio possa ser repetida
+pub fn map_range(start: VirtAddr, size: u64, flags: PageTableFlags) -> Result<(), MapToError<Size4KiB>> {
+    let flags = flags | PageTableFlags::PRESENT;
+    without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let (mapper, frame_allocator) = match (mapper.as_mut(), frame_allocator.as_mut()) {
+            (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+            _ => return Err(MapToError::FrameAllocationFailed),
+        };
+
+        let end = (start + size).align_up(PAGE_SIZE).as_u64();
+        let mut addr = start.align_down(PAGE_SIZE).as_u64();
+        while addr < end {
+            let virt = VirtAddr::new(addr);
+            if fits_huge_page(addr, end) {
+                if let Some(frame) = FrameAllocator::<Size2MiB>::allocate_frame(frame_allocator) {
+                    match unsafe { mapper.map_to(Page::<Size2MiB>::containing_address(virt), frame, flags, frame_allocator) } {
+                        Ok(flush) => {
+                            flush.flush();
+                            unsafe { core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0, HUGE_PAGE_SIZE as usize) };
+                            HUGE_PAGES.fetch_add(1, Ordering::SeqCst);
+                            addr += HUGE_PAGE_SIZE;
+                            continue;
+                        }
+                        // parte do pedaço já tem páginas de 4 KiB, segue por elas
+                        Err(_) => unsafe { FrameDeallocator::<Size2MiB>::deallocate_frame(frame_allocator, frame) },
+                    }
+                }
+            }
+
+            if mapper.translate_addr(virt).is_none() {
+                let frame = FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator)
+                    .ok_or(MapToError::FrameAllocationFailed)?;
+                unsafe {
+                    mapper.map_to(Page::<Size4KiB>::containing_address(virt), frame, flags, frame_allocator)?.flush();
+                    core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0, PAGE_SIZE as usize);
+                }
+            }
+            addr += PAGE_SIZE;
+        }
+        Ok(())
+    })
+}
+
+//mapeia `size` bytes de memória de dispositivo a partir de `phys` em `start`; quando os dois
+//lados estão alinhados em 2 MiB usa páginas grandes
+//
+//unsafe: `phys` não pode ser memória que o alocador de frames entrega
+pub unsafe fn map_device_range(
+    start: VirtAddr,
+    phys: PhysAddr,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<Size4KiB>> {
+    let flags = flags | PageTableFlags::PRESENT;
+    let result = without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let (mapper, frame_allocator) = match (mapper.as_mut(), frame_allocator.as_mut()) {
+            (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+            _ => return Err(MapToError::FrameAllocationFailed),
+        };
+
+        let mut offset = 0;
+        while offset < size {
+            let virt = start + offset;
+            let frame_addr = phys + offset;
+            if fits_huge_page(virt.as_u64(), start.as_u64() + size) && frame_addr.is_aligned(HUGE_PAGE_SIZE) {
+                let page = Page::<Size2MiB>::containing_address(virt);
+                let frame = PhysFrame::<Size2MiB>::containing_address(frame_addr);
+                if let Ok(flush) = mapper.map_to(page, frame, flags, frame_allocator) {
+                    flush.flush();
+                    HUGE_PAGES.fetch_add(1, Ordering::SeqCst);
+                    offset += HUGE_PAGE_SIZE;
+                    continue;
+                }
+            }
+
+            let page = Page::<Size4KiB>::containing_address(virt);
+            let frame = PhysFrame::<Size4KiB>::containing_address(frame_addr);
+            mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+            offset += PAGE_SIZE;
+        }
+        Ok(())
+    });
+
+    // nada fica pela metade
+    if result.is_err() {
+        unmap(start, size, false);
+    }
+    result
+}
+
+// desmapeia o intervalo; páginas grandes só saem se estiverem inteiras dentro dele.
+// `free` diz se os frames voltam para o alocador de frames
+unsafe fn unmap(start: VirtAddr, size: u64, free: bool) {
+    without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let (mapper, frame_allocator) = match (mapper.as_mut(), frame_allocator.as_mut()) {
+            (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+            _ => return,
+        };
+
+        let end = (start + size).align_up(PAGE_SIZE).as_u64();
+        let mut addr = start.align_down(PAGE_SIZE).as_u64();
+        while addr < end {
+            let virt = VirtAddr::new(addr);
+            match mapper.unmap(Page::<Size4KiB>::containing_address(virt)) {
+                Ok((frame, flush)) => {
+                    flush.flush();
+                    if free {
+                        frame_allocator.deallocate_frame(frame);
+                    }
+                    addr += PAGE_SIZE;
+                }
+                Err(UnmapError::ParentEntryHugePage) => {
+                    let page = Page::<Size2MiB>::containing_address(virt);
+                    let huge_start = page.start_address().as_u64();
+                    if huge_start >= start.as_u64() && huge_start + HUGE_PAGE_SIZE <= end {
+                        if let Ok((frame, flush)) = mapper.unmap(page) {
+                            flush.flush();
+                            HUGE_PAGES.fetch_sub(1, Ordering::SeqCst);
+                            if free {
+                                frame_allocator.deallocate_frame(frame);
+                            }
+                        }
+                    }
+                    addr = huge_start + HUGE_PAGE_SIZE;
+                }
+                Err(_) => addr += PAGE_SIZE,
+            }
+        }
+    })
+}
+
+//desmapeia [start, start + size) e devolve os frames ao alocador de frames, páginas que
+//não estavam mapeadas são puladas
+//
+//unsafe: nada pode continuar usando a memória do intervalo
+pub unsafe fn unmap_range(start: VirtAddr, size: u64) {
+    unmap(start, size, true)
+}
+
+//igual a `unmap_range`, mas os frames são de um dispositivo e não voltam para o alocador
+//
+//unsafe: nada pode continuar usando a memória do intervalo
+pub unsafe fn unmap_device_range(start: VirtAddr, size: u64) {
+    unmap(start, size, false)
+}
+
+// a tabela para onde `entry` aponta, criada vazia se a entrada ainda não existe
+fn next_table<'a>(
+    entry: &'a mut PageTableEntry,
+    offset: VirtAddr,
+    frame_allocator: &mut BootInfoFrameAllocator,
+) -> Result<&'a mut PageTable, MapToError<Size4KiB>> {
+    if entry.is_unused() {
+        let frame = FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator)
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        unsafe { core::ptr::write_bytes((offset + frame.start_address().as_u64()).as_mut_ptr::<u8>(), 0, PAGE_SIZE as usize) };
+        entry.set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+    } else if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+        return Err(MapToError::ParentEntryHugePage);
+    }
+    Ok(unsafe { &mut *(offset + entry.addr().as_u64()).as_mut_ptr::<PageTable>() })
+}
+
+//garante que as entradas do nível 4 que cobrem [start, start + size) apontem para uma tabela
+//de nível 3, mesmo vazia. os espaços de endereçamento copiam só o nível 4 do kernel, então
+//o que o kernel mapear depois embaixo dessas entradas aparece em todos eles
+pub fn share_level_4_entries(start: VirtAddr, size: u64) -> Result<(), MapToError<Size4KiB>> {
+    without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let (mapper, frame_allocator) = match (mapper.as_mut(), frame_allocator.as_mut()) {
+            (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+            _ => return Err(MapToError::FrameAllocationFailed),
+        };
+
+        let offset = mapper.phys_offset();
+        let first = usize::from(start.p4_index());
+        let last = usize::from((start + (size - 1)).p4_index());
+        let table = mapper.level_4_table_mut();
+        for entry in table.iter_mut().take(last + 1).skip(first) {
+            next_table(entry, offset, frame_allocator)?;
+        }
+        Ok(())
+    })
+}
+
+//prepara [start, start + size) de um intervalo com paginação sob demanda para ser tocado:
+//cria as tabelas que faltam e separa um frame para cada página sem mapeamento, assim o page
+//fault só pega o que já é dele e não falha por falta de memória. a reserva acaba quando
+//todas as páginas forem tocadas; um erro aqui é falta de memória (ou um intervalo fora de
+//uma região com paginação sob demanda)
+pub fn commit_demand_range(start: VirtAddr, size: u64) -> Result<(), MapToError<Size4KiB>> {
+    if size == 0 {
+        return Ok(());
+    }
+    without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        let (mapper, frame_allocator) = match (mapper.as_mut(), frame_allocator.as_mut()) {
+            (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+            _ => return Err(MapToError::FrameAllocationFailed),
+        };
+        let mut regions = REGIONS.lock();
+        let end = start.as_u64() + size;
+        let region = match regions.iter_mut().find(|r| r.contains(start)) {
+            Some(region) if region.demand.is_some() && end <= region.end() => region,
+            _ => return Err(MapToError::FrameAllocationFailed),
+        };
+
+        // o intervalo cresce do começo para o fim: o que está antes de `committed` já tem
+        // tabelas e reserva (mesmo sem ter sido tocado), só o resto entra na conta. um buraco
+        // entre o fim preparado e `start` também passa a valer, então é preparado junto
+        let from = region.start.as_u64() + region.committed;
+        if end <= from {
+            return Ok(());
+        }
+        let offset = mapper.phys_offset();
+        let first = Page::<Size4KiB>::containing_address(VirtAddr::new(from));
+        let last = Page::<Size4KiB>::containing_address(VirtAddr::new(end - 1));
+        let unmapped = Page::range_inclusive(first, last)
+            .filter(|page| mapper.translate_addr(page.start_address()).is_none())
+            .count();
+        // reserva antes das tabelas, um intervalo grande demais falha sem gastar frame nenhum
+        if !frame_allocator.reserve(unmapped) {
+            return Err(MapToError::FrameAllocationFailed);
+        }
+        for page in Page::range_inclusive(first, last) {
+            // a tabela de nível 1 serve 512 páginas, depois da primeira as outras já acham tudo
+            let level_4 = mapper.level_4_table_mut();
+            let tables = next_table(&mut level_4[page.p4_index()], offset, frame_allocator)
+                .and_then(|level_3| next_table(&mut level_3[page.p3_index()], offset, frame_allocator))
+                .and_then(|level_2| next_table(&mut level_2[page.p2_index()], offset, frame_allocator));
+            match tables {
+                Ok(_) => {}
+                // uma página grande já cobre este pedaço, não há nada para preparar nele
+                Err(MapToError::ParentEntryHugePage) => {}
+                Err(err) => {
+                    frame_allocator.unreserve(unmapped);
+                    return Err(err);
+                }
+            }
+        }
+
+        region.committed = end - region.start.as_u64();
+        region.reserved += unmapped;
+        Ok(())
+    })
+}
+
+//troca as flags das páginas já mapeadas em [start, start + size), páginas sem mapeamento
+//são puladas; entende as páginas de 2 MiB que o bootloader usa no mapa da memória física
+//(essas mudam inteiras, mesmo que só parte delas esteja no intervalo)
+pub fn set_flags(start: VirtAddr, size: u64, flags: PageTableFlags) {
+    let flags = flags | PageTableFlags::PRESENT;
+    without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mapper = match mapper.as_mut() {
+            Some(mapper) => mapper,
+            None => return,
+        };
+
+        let end = start.as_u64() + size;
+        let mut addr = start.align_down(PAGE_SIZE).as_u64();
+        while addr < end {
+            let virt = VirtAddr::new(addr);
+            match unsafe { mapper.update_flags(Page::<Size4KiB>::containing_address(virt), flags) } {
+                Ok(flush) => {
+                    flush.flush();
+                    addr += PAGE_SIZE;
+                }
+                Err(FlagUpdateError::ParentEntryHugePage) => {
+                    let page = Page::<Size2MiB>::containing_address(virt);
+                    if let Ok(flush) = unsafe { mapper.update_flags(page, flags) } {
+                        flush.flush();
+                    }
+                    addr = page.start_address().as_u64() + Size2MiB::SIZE;
+                }
+                Err(FlagUpdateError::PageNotMapped) => addr += PAGE_SIZE,
+            }
+        }
+    })
+}
@@ -0,0 +1,105 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use gale_sys::address_space::{self, AddressSpace, USER_START};
+use gale_sys::memory::{self, MAPPER};
+use x86_64::structures::paging::{PageTableFlags, Translate};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::BootInfoFrameAllocator;
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    address_space::activate_kernel();
+    gale_sys::test_panic_handler(info)
+}
+
+fn used_frames() -> usize {
+    memory::frame_stats().unwrap().used_frames
+}
+
+#[test_case]
+fn user_mappings_stay_out_of_the_kernel_table() {
+    let addr = VirtAddr::new(USER_START);
+    let mut space = AddressSpace::new().unwrap();
+    space.map_user(addr, 4096, PageTableFlags::WRITABLE).unwrap();
+
+    let (_, flags) = space.translate(addr).expect("user page not mapped");
+    assert!(flags.contains(PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE));
+    let in_kernel = x86_64::instructions::interrupts::without_interrupts(|| {
+        MAPPER.lock().as_ref().unwrap().translate_addr(addr)
+    });
+    assert_eq!(in_kernel, None);
+}
+
+#[test_case]
+fn active_space_still_sees_the_kernel() {
+    let addr = VirtAddr::new(USER_START);
+    let heap_value = Box::new(0x5eed_u64);
+    let mut space = AddressSpace::new().unwrap();
+    space.map_user(addr, 4096, PageTableFlags::WRITABLE).unwrap();
+
+    unsafe { space.activate() };
+    assert!(space.is_active());
+    let ptr = addr.as_mut_ptr::<u64>();
+    unsafe {
+        assert_eq!(ptr.read_volatile(), 0);
+        ptr.write_volatile(42);
+        assert_eq!(ptr.read_volatile(), 42);
+    }
+    // heap e heap novo (demand paging embaixo da entrada compartilhada) funcionam aqui dentro
+    assert_eq!(*heap_value, 0x5eed);
+    let fresh = Box::new([7u8; 8192]);
+    assert!(fresh.iter().all(|&b| b == 7));
+    address_space::activate_kernel();
+    assert!(!space.is_active());
+}
+
+#[test_case]
+fn spaces_map_the_same_address_to_different_frames() {
+    let addr = VirtAddr::new(USER_START + 0x20_0000);
+    let mut a = AddressSpace::new().unwrap();
+    let mut b = AddressSpace::new().unwrap();
+    a.map_user(addr, 4096, PageTableFlags::WRITABLE).unwrap();
+    b.map_user(addr, 4096, PageTableFlags::empty()).unwrap();
+
+    let (phys_a, flags_a) = a.translate(addr).unwrap();
+    let (phys_b, flags_b) = b.translate(addr).unwrap();
+    assert_ne!(phys_a, phys_b);
+    assert!(flags_a.contains(PageTableFlags::WRITABLE));
+    assert!(!flags_b.contains(PageTableFlags::WRITABLE));
+}
+
+#[test_case]
+fn dropping_a_space_returns_its_frames() {
+    let before = used_frames();
+    {
+        let mut space = AddressSpace::new().unwrap();
+        space.map_user(VirtAddr::new(USER_START), 16 * 4096, PageTableFlags::WRITABLE).unwrap();
+        // nível 4 + 3 tabelas intermediárias + 16 páginas
+        assert_eq!(used_frames(), before + 4 + 16);
+    }
+    assert_eq!(used_frames(), before);
+}
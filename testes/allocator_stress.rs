@@ -0,0 +1,209 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+// milhares de alloc/realloc/free aleatórios passando pelo #[global_allocator]; cada bloco
+// é preenchido com um padrão próprio e conferido antes de ser mexido de novo. A semente
+// de cada rodada vai para a serial e, se algo falhar, pode ser repetida com
+// STRESS_SEED=<semente> cargo test --test allocator_stress
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, alloc_zeroed, dealloc, realloc, Layout};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use gale_sys::serial_println;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+// semente da rodada em andamento, para o panic dizer qual repetir
+static CURRENT_SEED: AtomicU64 = AtomicU64::new(0);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    serial_println!("\nreplay with STRESS_SEED={:#x}", CURRENT_SEED.load(Ordering::SeqCst));
+    gale_sys::test_panic_handler(info)
+}
+
+const ROUNDS: u64 = 8;
+const STEPS: usize = 2000;
+const MAX_LIVE: usize = 128;
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// a semente pedida em STRESS_SEED, senão uma nova a cada boot
+fn base_seed() -> u64 {
+    if let Some(seed) = option_env!("STRESS_SEED") {
+        let seed = seed.trim_start_matches("0x");
+        return u64::from_str_radix(seed, 16).expect("STRESS_SEED must be hexadecimal");
+    }
+    // xorshift não sai do zero
+    let tsc = unsafe { core::arch::x86_64::_rdtsc() };
+    tsc | 1
+}
+
+#[derive(Clone, Copy)]
+struct Block {
+    ptr: *mut u8,
+    layout: Layout,
+    fill: u8,
+}
+
+// o byte `i` de um bloco depende do bloco e da posição, assim um bloco sobreposto ou
+// deslocado não passa na conferência
+fn pattern(fill: u8, i: usize) -> u8 {
+    fill.wrapping_add((i as u8).wrapping_mul(31))
+}
+
+unsafe fn write_pattern(block: &Block) {
+    for i in 0..block.layout.size() {
+        *block.ptr.add(i) = pattern(block.fill, i);
+    }
+}
+
+unsafe fn check_pattern(block: &Block, len: usize) {
+    for i in 0..len {
+        let found = *block.ptr.add(i);
+        assert_eq!(
+            found, pattern(block.fill, i),
+            "block {:p} (size {}, align {}) corrupted at offset {}",
+            block.ptr, block.layout.size(), block.layout.align(), i
+        );
+    }
+}
+
+fn random_layout(rng: &mut Rng) -> Layout {
+    let size = match rng.below(10) {
+        0..=4 => 1 + rng.below(256),
+        5..=8 => 257 + rng.below(4096 - 256),
+        _ => 4097 + rng.below(16 * 1024),
+    };
+    // alinhamentos grandes são raros, como no resto do kernel
+    let align = match rng.below(10) {
+        0..=6 => 1 << rng.below(4),
+        7..=8 => 1 << rng.below(9),
+        _ => 4096,
+    };
+    Layout::from_size_align(size, align).unwrap()
+}
+
+fn assert_disjoint(live: &[Block], new: &Block) {
+    let (start, end) = (new.ptr as usize, new.ptr as usize + new.layout.size());
+    for block in live {
+        let (other_start, other_end) = (block.ptr as usize, block.ptr as usize + block.layout.size());
+        assert!(
+            end <= other_start || other_end <= start,
+            "block {:p} (size {}) overlaps live block {:p} (size {})",
+            new.ptr, new.layout.size(), block.ptr, block.layout.size()
+        );
+    }
+}
+
+unsafe fn run_round(seed: u64) {
+    let mut rng = Rng(seed);
+    let mut live = [Block { ptr: core::ptr::null_mut(), layout: Layout::new::<u8>(), fill: 0 }; MAX_LIVE];
+    let mut count = 0;
+
+    for step in 0..STEPS {
+        match rng.below(4) {
+            // aloca, às vezes zerado
+            0 | 1 if count < MAX_LIVE => {
+                let layout = random_layout(&mut rng);
+                let zeroed = rng.below(4) == 0;
+                let ptr = if zeroed { alloc_zeroed(layout) } else { alloc(layout) };
+                assert!(!ptr.is_null(), "allocation of {:?} failed at step {}", layout, step);
+                assert_eq!(ptr as usize % layout.align(), 0);
+                if zeroed {
+                    assert!((0..layout.size()).all(|i| *ptr.add(i) == 0));
+                }
+
+                let block = Block { ptr, layout, fill: rng.next() as u8 };
+                assert_disjoint(&live[..count], &block);
+                write_pattern(&block);
+                live[count] = block;
+                count += 1;
+            }
+            // muda de tamanho, o começo tem que continuar igual
+            2 if count > 0 => {
+                let index = rng.below(count);
+                let block = live[index];
+                let new_size = random_layout(&mut rng).size();
+                let ptr = realloc(block.ptr, block.layout, new_size);
+                assert!(!ptr.is_null(), "realloc to {} failed at step {}", new_size, step);
+
+                let moved = Block { ptr, layout: Layout::from_size_align(new_size, block.layout.align()).unwrap(), ..block };
+                check_pattern(&moved, block.layout.size().min(new_size));
+                live[index] = live[count - 1];
+                count -= 1;
+                assert_disjoint(&live[..count], &moved);
+                write_pattern(&moved);
+                live[count] = moved;
+                count += 1;
+            }
+            // confere e libera
+            3 if count > 0 => {
+                let index = rng.below(count);
+                let block = live[index];
+                check_pattern(&block, block.layout.size());
+                dealloc(block.ptr, block.layout);
+                live[index] = live[count - 1];
+                count -= 1;
+            }
+            _ => {}
+        }
+
+        // de tempos em tempos confere todos os vivos, pega escrita em bloco alheio
+        if step % 256 == 0 {
+            for block in &live[..count] {
+                check_pattern(block, block.layout.size());
+            }
+        }
+    }
+
+    for block in &live[..count] {
+        check_pattern(block, block.layout.size());
+        dealloc(block.ptr, block.layout);
+    }
+}
+
+#[test_case]
+fn random_alloc_realloc_free() {
+    let base = base_seed();
+    for round in 0..ROUNDS {
+        let seed = base.wrapping_add(round) | 1;
+        CURRENT_SEED.store(seed, Ordering::SeqCst);
+        serial_println!("\n  round {} seed {:#x}", round, seed);
+        unsafe { run_round(seed) };
+    }
+}
@@ -0,0 +1,44 @@
+#![no_main]
+#![no_std]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use gale_sys::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    serial_print!("double_free::double_free_panics...\t");
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    // o alocador de depuração tem que parar no segundo free
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        dealloc(ptr, layout);
+        dealloc(ptr, layout);
+    }
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    gale_sys::hlt_loop();
+}
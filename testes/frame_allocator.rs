@@ -0,0 +1,96 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use gale_sys::memory::BootInfoFrameAllocator;
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame};
+
+static FRAME_ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use x86_64::VirtAddr;
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    gale_sys::test_panic_handler(info)
+}
+
+#[test_case]
+fn allocate_returns_distinct_frames() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let a: PhysFrame = allocator.allocate_frame().expect("no frame available");
+    let b: PhysFrame = allocator.allocate_frame().expect("no frame available");
+    assert_ne!(a, b);
+
+    unsafe {
+        allocator.deallocate_frame(a);
+        allocator.deallocate_frame(b);
+    }
+}
+
+#[test_case]
+fn counters_follow_allocations() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let free_before = allocator.free_frames();
+    let frame: PhysFrame = allocator.allocate_frame().expect("no frame available");
+    assert_eq!(allocator.free_frames(), free_before - 1);
+    assert_eq!(allocator.used_frames() + allocator.free_frames(), allocator.total_frames());
+
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.free_frames(), free_before);
+}
+
+#[test_case]
+fn freed_frame_is_reused() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let frame: PhysFrame = allocator.allocate_frame().expect("no frame available");
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.allocate_frame(), Some(frame));
+    unsafe { allocator.deallocate_frame(frame) };
+}
+
+#[test_case]
+fn huge_frames_are_aligned_runs() {
+    use x86_64::structures::paging::{PageSize, Size2MiB};
+
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+    let used = allocator.used_frames();
+
+    let huge: PhysFrame<Size2MiB> = allocator.allocate_frame().expect("no 2 MiB run available");
+    assert!(huge.start_address().is_aligned(Size2MiB::SIZE));
+    assert_eq!(allocator.used_frames(), used + 512);
+
+    // nenhum frame de 4 KiB pode sair de dentro dele
+    let small: PhysFrame = allocator.allocate_frame().expect("no frame available");
+    let huge_range = huge.start_address()..huge.start_address() + Size2MiB::SIZE;
+    assert!(!huge_range.contains(&small.start_address()));
+
+    unsafe {
+        allocator.deallocate_frame(small);
+        allocator.deallocate_frame(huge);
+    }
+    assert_eq!(allocator.used_frames(), used);
+}
@@ -0,0 +1,302 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    gale_sys::test_panic_handler(info)
+}
+
+#[test_case]
+fn simple_allocation() {
+    let heap_value_1 = Box::new(41);
+    let heap_value_2 = Box::new(13);
+    assert_eq!(*heap_value_1, 41);
+    assert_eq!(*heap_value_2, 13);
+}
+
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+
+#[test_case]
+fn string_concat() {
+    let mut s = String::new();
+    for word in ["gale", "_", "sys"] {
+        s.push_str(word);
+    }
+    assert_eq!(s.as_str(), "gale_sys");
+}
+
+#[test_case]
+fn many_boxes() {
+    for i in 0..gale_sys::combined_allocator::HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+
+#[test_case]
+fn heap_grows_past_initial_size() {
+    use gale_sys::combined_allocator::HEAP_SIZE;
+
+    // blocos vivos ao mesmo tempo somando mais que o heap mapeado no boot
+    let block_size = 2048;
+    let count = 2 * HEAP_SIZE / block_size;
+    let mut blocks = Vec::with_capacity(count);
+    for i in 0..count {
+        blocks.push(Box::new([i as u8; 2048]));
+    }
+    for (i, block) in blocks.iter().enumerate() {
+        assert_eq!(block[0], i as u8);
+        assert_eq!(block[block_size - 1], i as u8);
+    }
+}
+
+#[test_case]
+fn multi_page_buffer() {
+    let mut buffer = Vec::<u8>::with_capacity(16 * 1024);
+    buffer.resize(16 * 1024, 0xAB);
+    assert!(buffer.iter().all(|&b| b == 0xAB));
+}
+
+#[test_case]
+fn vec_grows_past_one_page() {
+    let mut vec = Vec::new();
+    for i in 0..4096u64 {
+        vec.push(i);
+    }
+    assert_eq!(vec[4095], 4095);
+}
+
+#[test_case]
+fn page_aligned_allocation() {
+    use alloc::alloc::{alloc, dealloc, Layout};
+
+    let layout = Layout::from_size_align(8192, 4096).unwrap();
+    let ptr = unsafe { alloc(layout) };
+    assert!(!ptr.is_null());
+    assert_eq!(ptr as usize % 4096, 0);
+    unsafe { dealloc(ptr, layout) };
+}
+
+#[test_case]
+fn reuse_respects_size_and_alignment() {
+    use alloc::alloc::{alloc, dealloc, Layout};
+
+    let small = Layout::from_size_align(24, 8).unwrap();
+    let big = Layout::from_size_align(900, 512).unwrap();
+
+    unsafe {
+        let a = alloc(small);
+        dealloc(a, small);
+
+        // um bloco de 24 bytes liberado não pode voltar para um pedido de 900
+        let b = alloc(big);
+        assert!(!b.is_null());
+        assert_eq!(b as usize % 512, 0);
+        assert_ne!(a, b);
+
+        // dois blocos vivos nunca compartilham endereço
+        let c = alloc(small);
+        let d = alloc(small);
+        assert_ne!(c, d);
+
+        dealloc(b, big);
+        dealloc(c, small);
+        dealloc(d, small);
+    }
+}
+
+#[test_case]
+fn realloc_keeps_contents() {
+    use alloc::alloc::{alloc, alloc_zeroed, dealloc, realloc, Layout};
+
+    unsafe {
+        let layout = Layout::from_size_align(8192, 8).unwrap();
+        let ptr = alloc_zeroed(layout);
+        assert!(!ptr.is_null());
+        assert!((0..8192).all(|i| *ptr.add(i) == 0));
+
+        for i in 0..8192 {
+            *ptr.add(i) = i as u8;
+        }
+        let grown = realloc(ptr, layout, 3 * 8192);
+        assert!(!grown.is_null());
+        assert!((0..8192).all(|i| *grown.add(i) == i as u8));
+        dealloc(grown, Layout::from_size_align(3 * 8192, 8).unwrap());
+
+        let layout = Layout::from_size_align(600, 8).unwrap();
+        let ptr = alloc(layout);
+        *ptr = 7;
+        let shrunk = realloc(ptr, layout, 300);
+        assert_eq!(*shrunk, 7);
+        dealloc(shrunk, Layout::from_size_align(300, 8).unwrap());
+    }
+}
+
+#[test_case]
+fn small_blocks_do_not_overlap() {
+    // mais blocos pequenos do que cabem numa página de slab
+    let mut boxes = Vec::new();
+    for i in 0..1000u64 {
+        boxes.push(Box::new(i));
+    }
+    for (i, value) in boxes.iter().enumerate() {
+        assert_eq!(**value, i as u64);
+    }
+
+    // liberar no meio não pode mexer nos blocos vizinhos
+    for i in (0..boxes.len()).step_by(2).rev() {
+        boxes.remove(i);
+    }
+    for (i, value) in boxes.iter().enumerate() {
+        assert_eq!(**value, (2 * i + 1) as u64);
+    }
+}
+
+// o alocador de depuração soma cabeçalho e zonas vermelhas e segura os blocos na quarentena
+#[cfg(not(feature = "alloc-debug"))]
+#[test_case]
+fn stats_track_live_bytes() {
+    use gale_sys::meminfo::heap_stats;
+
+    let before = heap_stats();
+    let block = Box::new([0u8; 100]);
+    let during = heap_stats();
+    // 100 bytes caem no bloco de 128 nos dois alocadores
+    assert_eq!(during.bytes_allocated, before.bytes_allocated + 128);
+    assert!(during.peak_allocated >= during.bytes_allocated);
+    assert!(during.bytes_allocated + during.bytes_free <= during.heap_size);
+    drop(block);
+    assert_eq!(heap_stats().bytes_allocated, before.bytes_allocated);
+}
+
+#[cfg(feature = "alloc-debug")]
+#[test_case]
+fn debug_allocator_catches_overflow_and_double_free() {
+    use alloc::alloc::{alloc, dealloc, Layout};
+    use gale_sys::debug_allocator::{Corruption, ALLOCATOR};
+
+    let layout = Layout::from_size_align(40, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        assert_eq!(ALLOCATOR.check(ptr, layout), Ok(()));
+        dealloc(ptr, layout);
+        assert_eq!(ALLOCATOR.check(ptr, layout), Err(Corruption::DoubleFree));
+
+        // um byte depois do fim estraga a zona vermelha de trás; o bloco fica vazado
+        // de propósito, liberar ele daria panic
+        let ptr = alloc(layout);
+        *ptr.add(40) = 0;
+        assert_eq!(ALLOCATOR.check(ptr, layout), Err(Corruption::BackRedZone));
+    }
+}
+
+#[cfg(feature = "alloc-leak")]
+#[test_case]
+fn leak_tracker_sees_live_allocations() {
+    use gale_sys::leak_tracker::{assert_no_leaks, assert_no_new_allocations, checkpoint, for_each_since};
+
+    let start = checkpoint();
+    let value = Box::new(7u64);
+    let mut found = None;
+    for_each_since(start, |a| found = Some(a));
+    let found = found.expect("allocation was not tracked");
+    assert_eq!(found.addr, &*value as *const u64 as usize);
+    assert_eq!(found.size, 8);
+
+    drop(value);
+    assert_no_new_allocations(start);
+    assert_no_leaks(|| {
+        let mut v = Vec::new();
+        v.extend_from_slice(b"gale_sys");
+        assert_eq!(v.len(), 8);
+    });
+}
+
+#[test_case]
+fn page_map_covers_the_heap() {
+    use gale_sys::combined_allocator::{HEAP_SIZE, HEAP_START};
+    use gale_sys::memory;
+    use x86_64::structures::paging::PageTableFlags;
+
+    let offset = memory::physical_memory_offset().expect("memory not initialized");
+    let (start, end) = (HEAP_START as u64, (HEAP_START + HEAP_SIZE) as u64);
+    // o heap é paginado sob demanda, uma leitura em cada página garante que todas existem
+    for page in (start..end).step_by(4096) {
+        unsafe { core::ptr::read_volatile(page as *const u8) };
+    }
+    let mut covered = 0;
+    let mut previous_end = 0;
+    unsafe {
+        gale_sys::pagemap::walk(offset, |mapping| {
+            let (virt, size) = (mapping.virt.as_u64(), mapping.size);
+            // os intervalos saem em ordem e sem se sobrepor
+            assert!(virt >= previous_end);
+            previous_end = virt.wrapping_add(size);
+            if virt < end && virt + size > start {
+                assert!(mapping.flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+                covered += (virt + size).min(end) - virt.max(start);
+            }
+        });
+    }
+    assert_eq!(covered, HEAP_SIZE as u64);
+}
+
+#[cfg(feature = "allocator-combined")]
+#[test_case]
+fn page_records_are_mapped_as_the_heap_grows() {
+    use gale_sys::memory::MAPPER;
+    use gale_sys::vmm;
+    use x86_64::structures::paging::Translate;
+    use x86_64::VirtAddr;
+
+    let mut records = None;
+    vmm::for_each(|region| {
+        if region.name == "heap pages" {
+            records = Some(region);
+        }
+    });
+    let records = records.expect("page record window not reserved");
+    // algumas páginas pequenas já existem, mas a janela inteira só seria mapeada com o heap cheio
+    let _small = Box::new(1u8);
+    let translate = |addr: u64| {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            MAPPER.lock().as_ref().unwrap().translate_addr(VirtAddr::new(addr))
+        })
+    };
+    assert!(translate(records.start.as_u64()).is_some());
+    assert!(translate(records.end() - 4096).is_none());
+}
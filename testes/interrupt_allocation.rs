@@ -0,0 +1,67 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use gale_sys::interrupts::set_timer_callback;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    gale_sys::test_panic_handler(info)
+}
+
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+// roda dentro do handler do timer, possivelmente no meio de uma alocação do main
+fn allocate_in_interrupt() {
+    let mut values = Vec::new();
+    for i in 0..64usize {
+        values.push(Box::new(i));
+    }
+    assert!(values.iter().enumerate().all(|(i, value)| **value == i));
+    TICKS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+fn allocation_from_timer_and_main() {
+    set_timer_callback(Some(allocate_in_interrupt));
+
+    // aloca sem parar até o timer ter disparado várias vezes no meio
+    let mut rounds = 0u64;
+    while TICKS.load(Ordering::SeqCst) < 20 {
+        let mut values = Vec::new();
+        for i in 0..128u64 {
+            values.push(Box::new(i));
+        }
+        let total: u64 = values.iter().map(|value| **value).sum();
+        assert_eq!(total, 127 * 128 / 2);
+        rounds += 1;
+    }
+
+    set_timer_callback(None);
+    assert!(rounds > 0);
+}
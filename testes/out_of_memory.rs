@@ -0,0 +1,91 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use gale_sys::combined_allocator::{ALLOCATOR, HEAP_MAX_SIZE};
+use gale_sys::{meminfo, oom};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    gale_sys::test_panic_handler(info)
+}
+
+const CHUNK: usize = 16 * 1024;
+const MAX_CHUNKS: usize = HEAP_MAX_SIZE / CHUNK;
+
+// o bloco que o callback solta quando o heap acaba
+static BALLAST: AtomicUsize = AtomicUsize::new(0);
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn release_ballast(_layout: Layout) -> bool {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+    let ptr = BALLAST.swap(0, Ordering::SeqCst);
+    if ptr == 0 {
+        return false;
+    }
+    unsafe { dealloc(ptr as *mut u8, Layout::from_size_align(CHUNK, 8).unwrap()) };
+    true
+}
+
+#[test_case]
+fn callback_frees_memory_for_failed_request() {
+    let layout = Layout::from_size_align(CHUNK, 8).unwrap();
+
+    // o heap não cresce mais, então ele acaba logo
+    ALLOCATOR.set_max_size(meminfo::heap_stats().heap_size);
+    let mut chunks = [core::ptr::null_mut(); MAX_CHUNKS];
+    let mut count = 0;
+    while count < MAX_CHUNKS {
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            break;
+        }
+        chunks[count] = ptr;
+        count += 1;
+    }
+    assert!(count > 1 && count < MAX_CHUNKS);
+
+    // um dos blocos vira o lastro do callback
+    count -= 1;
+    BALLAST.store(chunks[count] as usize, Ordering::SeqCst);
+    let id = oom::register(release_ballast).expect("oom callback table full");
+
+    let ptr = unsafe { alloc(layout) };
+    assert!(!ptr.is_null());
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(BALLAST.load(Ordering::SeqCst), 0);
+
+    oom::unregister(id);
+    unsafe {
+        dealloc(ptr, layout);
+        for &chunk in &chunks[..count] {
+            dealloc(chunk, layout);
+        }
+    }
+    ALLOCATOR.set_max_size(HEAP_MAX_SIZE);
+}
@@ -0,0 +1,79 @@
+#![no_main]
+#![no_std]
+
+use bootloader::{entry_point, BootInfo};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use gale_sys::{exit_qemu, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    serial_print!("stack_guard::overflow_names_the_stack...\t");
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    // roda a recursão numa pilha do vmm, a página de guarda dela tem que ser reconhecida
+    let stack = gale_sys::stack::allocate(gale_sys::stack::DEFAULT_STACK_SIZE, "test stack")
+        .expect("stack allocation failed");
+    unsafe {
+        core::arch::asm!(
+            "mov rsp, {top}",
+            "call {overflow}",
+            top = in(reg) stack.top().as_u64(),
+            overflow = sym overflow,
+            options(noreturn),
+        );
+    }
+}
+
+extern "C" fn overflow() -> ! {
+    stack_overflow();
+    serial_println!("[test did not overflow]");
+    exit_qemu(QemuExitCode::Failed);
+    gale_sys::hlt_loop();
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    volatile::Volatile::new(0).read();
+}
+
+// guarda o começo da mensagem do panic para comparar
+struct Message {
+    bytes: [u8; 64],
+    len: usize,
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut message = Message { bytes: [0; 64], len: 0 };
+    let _ = write!(message, "{}", info.message());
+    if &message.bytes[..message.len] == b"stack overflow in test stack" {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n");
+        serial_println!("Error: {}\n", info);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    gale_sys::hlt_loop();
+}
@@ -0,0 +1,208 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use gale_sys::memory;
+use gale_sys::vmm::{self, RegionKind, VmaError, GUARD_GAP};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::BootInfoFrameAllocator;
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    gale_sys::test_panic_handler(info)
+}
+
+#[test_case]
+fn boot_regions_are_reserved() {
+    use gale_sys::combined_allocator::HEAP_START;
+
+    let heap = vmm::find(VirtAddr::new(HEAP_START as u64)).expect("heap window not reserved");
+    assert_eq!(heap.kind, RegionKind::Heap);
+    let offset = memory::physical_memory_offset().unwrap();
+    assert_eq!(vmm::find(offset).map(|r| r.kind), Some(RegionKind::PhysicalMap));
+}
+
+#[test_case]
+fn allocations_keep_guard_gaps() {
+    let a = vmm::allocate(4 * 4096, 4096, RegionKind::Other, "a").unwrap();
+    let b = vmm::allocate(4096, 0x20_0000, RegionKind::Other, "b").unwrap();
+    assert_eq!(b.as_u64() % 0x20_0000, 0);
+
+    let mut previous: Option<vmm::Region> = None;
+    vmm::for_each(|region| {
+        if let Some(previous) = previous {
+            assert!(previous.end() <= region.start.as_u64());
+            if region.start == a || region.start == b {
+                assert!(previous.end() + GUARD_GAP <= region.start.as_u64());
+            }
+        }
+        previous = Some(region);
+    });
+
+    assert_eq!(vmm::reserve(a, 4096, RegionKind::Other, "overlap"), Err(VmaError::Overlap));
+    assert!(vmm::release(a).is_some());
+    assert!(vmm::release(b).is_some());
+    assert!(vmm::find(a).is_none());
+}
+
+#[test_case]
+fn unmap_returns_frames() {
+    let size = 8 * 4096;
+    let start = vmm::allocate(size, 4096, RegionKind::Other, "scratch").unwrap();
+    let before = memory::frame_stats().unwrap().used_frames;
+
+    vmm::map_range(start, size, PageTableFlags::WRITABLE).expect("map_range failed");
+    let bytes = unsafe { core::slice::from_raw_parts_mut(start.as_mut_ptr::<u8>(), size as usize) };
+    // frames novos chegam zerados
+    assert!(bytes.iter().all(|&b| b == 0));
+    bytes.fill(0x5a);
+    // as tabelas de página intermediárias também gastam frames
+    assert!(memory::frame_stats().unwrap().used_frames >= before + 8);
+
+    unsafe { vmm::unmap_range(start, size) };
+    vmm::release(start).unwrap();
+    let after = memory::frame_stats().unwrap().used_frames;
+    assert!(after <= before + 3, "{} frames not returned", after - before);
+}
+
+#[test_case]
+fn ioremap_reaches_device_memory() {
+    // o buffer de texto do vga é memória de dispositivo que sempre existe
+    const VGA: u64 = 0xb8000;
+    let phys = x86_64::PhysAddr::new(VGA + 2 * 80);
+    let mut mmio = unsafe { memory::ioremap::<u16>(phys, 2 * 80, "vga") }.expect("ioremap failed");
+    assert!(mmio.as_ptr() as u64 % 4096 == 160);
+    assert_eq!(vmm::find(VirtAddr::from_ptr(mmio.as_ptr())).map(|r| r.kind), Some(RegionKind::Mmio));
+
+    mmio.write(0x2f41);
+    mmio.write_at::<u16>(2, 0x2f42);
+    // o mesmo frame visto pelo mapa da memória física
+    let offset = memory::physical_memory_offset().unwrap();
+    let direct = (offset + phys.as_u64()).as_ptr::<u16>();
+    assert_eq!(unsafe { core::ptr::read_volatile(direct) }, 0x2f41);
+    assert_eq!(unsafe { core::ptr::read_volatile(direct.add(1)) }, 0x2f42);
+    assert_eq!(mmio.read_at::<u16>(2), 0x2f42);
+
+    let region = VirtAddr::from_ptr(mmio.as_ptr());
+    memory::iounmap(mmio);
+    assert!(vmm::find(region).is_none());
+}
+
+#[test_case]
+fn large_ranges_use_huge_pages() {
+    const HUGE: u64 = 2 * 1024 * 1024;
+
+    let start = vmm::allocate(2 * HUGE, HUGE, RegionKind::Other, "huge").unwrap();
+    let pages = vmm::huge_page_stats().huge_pages;
+    let frames = memory::frame_stats().unwrap().used_frames;
+
+    vmm::map_range(start, 2 * HUGE, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE)
+        .expect("map_range failed");
+    let stats = vmm::huge_page_stats();
+    assert_eq!(stats.huge_pages, pages + 2);
+    assert_eq!(stats.tlb_entries_saved, stats.huge_pages * 511);
+
+    let bytes = unsafe { core::slice::from_raw_parts_mut(start.as_mut_ptr::<u8>(), 2 * HUGE as usize) };
+    assert!(bytes.iter().all(|&b| b == 0));
+    bytes.fill(0x5a);
+
+    unsafe { vmm::unmap_range(start, 2 * HUGE) };
+    vmm::release(start).unwrap();
+    assert_eq!(vmm::huge_page_stats().huge_pages, pages);
+    // só as tabelas intermediárias continuam
+    assert!(memory::frame_stats().unwrap().used_frames <= frames + 2);
+}
+
+#[test_case]
+fn demand_paged_region_maps_on_first_touch() {
+    use x86_64::structures::paging::Translate;
+
+    let size = 16 * 4096;
+    let start = vmm::allocate(size, 4096, RegionKind::Other, "demand").unwrap();
+    assert!(vmm::set_demand_paged(start, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+    let reserved = || memory::frame_stats().unwrap().reserved_frames;
+    let before = reserved();
+    // cada página ganha um frame reservado, o page fault só usa esses
+    vmm::commit_demand_range(start, size).unwrap();
+    assert_eq!(reserved(), before + 16);
+    let faults = vmm::demand_faults();
+
+    let translate = |addr: VirtAddr| memory::MAPPER.lock().as_ref().unwrap().translate_addr(addr);
+    let page = start + 3 * 4096;
+    assert!(translate(page).is_none());
+
+    // a escrita cai num page fault, o handler mapeia um frame zerado e a escrita é repetida
+    unsafe { core::ptr::write_volatile((page + 8u64).as_mut_ptr::<u64>(), 0xfeed) };
+    assert_eq!(vmm::demand_faults(), faults + 1);
+    assert_eq!(reserved(), before + 15);
+    assert!(translate(page).is_some());
+    assert!(translate(start).is_none());
+    let bytes = unsafe { core::slice::from_raw_parts(page.as_ptr::<u64>(), 512) };
+    assert_eq!(bytes[1], 0xfeed);
+    assert!(bytes.iter().enumerate().all(|(i, &b)| i == 1 || b == 0));
+
+    unsafe { vmm::unmap_range(start, size) };
+    // as 15 páginas que nunca foram tocadas devolvem a reserva
+    assert_eq!(vmm::release(start).unwrap().reserved, 15);
+    assert_eq!(reserved(), before);
+}
+
+#[test_case]
+fn demand_commit_twice_reserves_once() {
+    let size = 8 * 4096;
+    // alinhado a 2 MiB, uma tabela de nível 1 serve os dois pedaços
+    let start = vmm::allocate(2 * size, 0x20_0000, RegionKind::Other, "demand twice").unwrap();
+    assert!(vmm::set_demand_paged(start, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+    let stats = || {
+        let stats = memory::frame_stats().unwrap();
+        (stats.free_frames, stats.reserved_frames)
+    };
+
+    vmm::commit_demand_range(start, size).unwrap();
+    let committed = stats();
+    // as páginas ainda não foram tocadas, mas já têm reserva: repetir não muda nada
+    vmm::commit_demand_range(start, size).unwrap();
+    assert_eq!(stats(), committed);
+    // um pedaço que só sobrepõe uma parte reserva apenas as páginas novas
+    vmm::commit_demand_range(start + 4 * 4096u64, size).unwrap();
+    assert_eq!(stats(), (committed.0, committed.1 + 4));
+
+    unsafe { vmm::unmap_range(start, 2 * size) };
+    assert_eq!(vmm::release(start).unwrap().reserved, 12);
+    assert_eq!(stats().1, committed.1 - 8);
+}
+
+#[test_case]
+fn demand_commit_fails_without_enough_frames() {
+    let frames = memory::frame_stats().unwrap();
+    let pages = (frames.free_frames - frames.reserved_frames + 1) as u64;
+    let start = vmm::allocate(pages * 4096, 4096, RegionKind::Other, "too big").unwrap();
+    assert!(vmm::set_demand_paged(start, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+
+    // nada é reservado nem gasto em tabelas, e um acesso continua fatal (o fault não é aceito)
+    assert!(vmm::commit_demand_range(start, pages * 4096).is_err());
+    let after = memory::frame_stats().unwrap();
+    assert_eq!((after.used_frames, after.reserved_frames), (frames.used_frames, frames.reserved_frames));
+    assert!(!vmm::handle_demand_fault(start));
+    vmm::release(start).unwrap();
+}
@@ -0,0 +1,143 @@
+#![no_main]
+#![no_std]
+#![feature(custom_test_frameworks)]
+#![feature(abi_x86_interrupt)]
+#![test_runner(gale_sys::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+// cada teste provoca um page fault de propósito; o handler daqui anota o erro, libera a
+// página (gravável ou executável) e deixa a instrução ser repetida, o teste devolve as flags
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags, Size4KiB, Translate};
+use x86_64::structures::paging::mapper::TranslateResult;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use gale_sys::memory::{self, BootInfoFrameAllocator};
+
+    gale_sys::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_heap(mapper, frame_allocator)
+        .expect("heap initialization failed");
+
+    // o IDT daqui não trata o timer
+    x86_64::instructions::interrupts::disable();
+    TEST_IDT.load();
+
+    test_main();
+    gale_sys::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    gale_sys::test_panic_handler(info)
+}
+
+// endereço e código de erro do último page fault
+static LAST_FAULT: Mutex<Option<(VirtAddr, PageFaultErrorCode)>> = Mutex::new(None);
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+// o resultado sai do lock antes do panic; com MAPPER pego nada pode tocar o heap
+fn page_flags(addr: VirtAddr) -> PageTableFlags {
+    let flags = match gale_sys::memory::MAPPER.lock().as_ref().unwrap().translate(addr) {
+        TranslateResult::Mapped { flags, .. } => Some(flags),
+        _ => None,
+    };
+    flags.unwrap_or_else(|| panic!("{:?} is not mapped", addr))
+}
+
+fn update_flags(addr: VirtAddr, flags: PageTableFlags) {
+    let mut mapper = gale_sys::memory::MAPPER.lock();
+    let page = Page::<Size4KiB>::containing_address(addr);
+    unsafe { mapper.as_mut().unwrap().update_flags(page, flags).unwrap().flush() };
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let addr = x86_64::registers::control::Cr2::read().unwrap();
+    // o heap é paginado sob demanda, esses faults não são dos testes
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) && gale_sys::vmm::handle_demand_fault(addr) {
+        return;
+    }
+    if LAST_FAULT.lock().replace((addr, error_code)).is_some() {
+        panic!("second page fault at {:?} ({:?})", addr, error_code);
+    }
+
+    let flags = page_flags(addr);
+    update_flags(addr, (flags | PageTableFlags::WRITABLE) - PageTableFlags::NO_EXECUTE);
+}
+
+fn take_fault() -> (VirtAddr, PageFaultErrorCode) {
+    LAST_FAULT.lock().take().expect("no page fault happened")
+}
+
+#[test_case]
+fn kernel_image_has_no_writable_code() {
+    gale_sys::memory::kernel_segments(|segment| {
+        let flags = page_flags(segment.start);
+        assert!(!(flags.contains(PageTableFlags::WRITABLE) && !flags.contains(PageTableFlags::NO_EXECUTE)));
+        assert_eq!(flags.contains(PageTableFlags::NO_EXECUTE), !segment.executable);
+    });
+}
+
+// uma função qualquer do .text
+#[inline(never)]
+fn code_target() {}
+
+#[test_case]
+fn writing_to_code_faults() {
+    let target = code_target as *const u8 as *mut u8;
+    let addr = VirtAddr::from_ptr(target);
+    let flags = page_flags(addr);
+
+    // escreve o mesmo byte, o código continua igual depois que o handler libera a página
+    unsafe {
+        let value = core::ptr::read_volatile(target);
+        core::ptr::write_volatile(target, value);
+    }
+
+    update_flags(addr, flags);
+    assert_eq!(
+        take_fault(),
+        (addr, PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE)
+    );
+}
+
+#[test_case]
+fn executing_from_the_heap_faults() {
+    // uma instrução `ret` num bloco do heap
+    let code = Box::new([0xc3u8; 1]);
+    let addr = VirtAddr::from_ptr(code.as_ptr());
+    let flags = page_flags(addr);
+    assert!(flags.contains(PageTableFlags::NO_EXECUTE));
+
+    let f: extern "C" fn() = unsafe { core::mem::transmute(code.as_ptr()) };
+    f();
+
+    update_flags(addr, flags);
+    assert_eq!(
+        take_fault(),
+        (addr, PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::INSTRUCTION_FETCH)
+    );
+}